@@ -0,0 +1,61 @@
+"""Python bindings of the rust-hive ingestion pipeline.
+
+The fetching, parsing, loading and exporting are done by the Rust crate, the
+functions below only hand its results over as Arrow tables or pandas frames.
+"""
+
+import duckdb
+import pandas as pd
+import pyarrow as pa
+
+from ._rust_hive import PopulationRow, export, fetch, ingest, parse
+
+WAREHOUSE_PATH = './datasets/thai_population.duckdb'
+
+__all__ = [
+	'PopulationRow',
+	'export',
+	'fetch',
+	'ingest',
+	'parse',
+	'parse_table',
+	'read_frame',
+	'read_table',
+]
+
+
+def parse_table(text: str, data_year: int | None = None) -> pa.Table:
+	"""Parse the text of a DOPA statistic file into an Arrow table
+
+	Args:
+	    text (str) - Content of a `stat_c` file, as returned by `fetch`
+	    data_year (int | None) - Year added as the first column when given
+	Returns:
+	    pa.Table - One row per line, with the columns of `thai_population`
+	"""
+	rows = [row.to_dict() for row in parse(text)]
+	if data_year is not None:
+		rows = [{'data_year': data_year, **row} for row in rows]
+	return pa.Table.from_pylist(rows)
+
+
+def read_table(
+	database: str = WAREHOUSE_PATH, query: str = 'FROM thai_population'
+) -> pa.Table:
+	"""Read the warehouse loaded by `ingest` or by the binaries
+
+	Args:
+	    database (str) - Path of the DuckDB warehouse
+	    query (str) - Query to run, the whole `thai_population` by default
+	Returns:
+	    pa.Table - The result of the query
+	"""
+	with duckdb.connect(database, read_only=True) as conn:
+		return conn.sql(query).arrow()
+
+
+def read_frame(
+	database: str = WAREHOUSE_PATH, query: str = 'FROM thai_population'
+) -> pd.DataFrame:
+	"""Same as `read_table`, as a pandas frame"""
+	return read_table(database, query).to_pandas()
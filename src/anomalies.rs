@@ -0,0 +1,171 @@
+use duckdb::{params, Connection, Result};
+use serde::Serialize;
+
+/// Modified z-score above which a year-over-year growth is flagged.
+pub const GROWTH_SCORE_THRESHOLD: f64 = 3.5;
+/// Change of the sex ratio, in males per 100 females, above which a year is flagged.
+pub const SEX_RATIO_SHIFT_THRESHOLD: f64 = 5.0;
+
+/// A year of an area whose counts look wrong compared with its other years.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Anomaly {
+    pub data_year: i32,
+    pub cc_code: i32,
+    pub cc_desc: String,
+    /// `growth_outlier`, `zero_count` or `sex_ratio_shift`.
+    pub kind: String,
+    /// The growth, the count or the change of sex ratio which was flagged.
+    pub value: f64,
+    /// How far the value is from normal, in the unit of its rule.
+    pub score: f64,
+}
+
+/// Rebuilds `thai_population_anomalies` from `thai_population`, comparing every year of a
+/// `cc_code` with the previous one.
+///
+/// * `growth_outlier` - the growth of `total` has a modified z-score, the distance to the
+///   median growth of the area in median absolute deviations, above
+///   `GROWTH_SCORE_THRESHOLD`. Areas whose growths barely vary fall back to the standard
+///   z-score.
+/// * `zero_count` - `male`, `female` or `total` drops to zero while it was not the year before.
+/// * `sex_ratio_shift` - the sex ratio moves by more than `SEX_RATIO_SHIFT_THRESHOLD` points.
+///
+/// # Arguments
+///
+/// * `conn` - A reference to a DuckDB Connection to the warehouse.
+///
+/// # Returns
+///
+/// * `Result<Vec<Anomaly>>` - The anomalies found, ordered by year and area.
+///
+pub fn detect_anomalies(conn: &Connection) -> Result<Vec<Anomaly>> {
+    conn.execute(
+        "CREATE OR REPLACE TABLE thai_population_anomalies AS
+        WITH changes AS (
+            SELECT
+                p.data_year,
+                p.cc_code,
+                p.cc_desc,
+                p.male,
+                p.female,
+                p.total,
+                previous.male AS previous_male,
+                previous.female AS previous_female,
+                previous.total AS previous_total,
+                (p.total - previous.total) / nullif(previous.total, 0) AS growth,
+                p.male / nullif(p.female, 0) * 100
+                    - previous.male / nullif(previous.female, 0) * 100 AS sex_ratio_shift
+            FROM thai_population p
+            JOIN thai_population previous
+                ON previous.cc_code = p.cc_code AND previous.data_year = p.data_year - 1
+        ),
+        scored AS (
+            SELECT
+                *,
+                CASE
+                    WHEN mad(growth) OVER area > 0
+                        THEN (growth - median(growth) OVER area)
+                            / (1.4826 * mad(growth) OVER area)
+                    WHEN stddev_samp(growth) OVER area > 0
+                        THEN (growth - avg(growth) OVER area) / stddev_samp(growth) OVER area
+                END AS growth_score
+            FROM changes
+            WINDOW area AS (PARTITION BY cc_code)
+        )
+        SELECT data_year, cc_code, cc_desc, 'growth_outlier' AS kind, growth AS value,
+            growth_score AS score
+        FROM scored
+        WHERE abs(growth_score) > ?
+        UNION ALL
+        SELECT data_year, cc_code, cc_desc, 'zero_count', 0, 0
+        FROM changes
+        WHERE (male = 0 AND previous_male > 0)
+            OR (female = 0 AND previous_female > 0)
+            OR (total = 0 AND previous_total > 0)
+        UNION ALL
+        SELECT data_year, cc_code, cc_desc, 'sex_ratio_shift', sex_ratio_shift,
+            sex_ratio_shift / ?
+        FROM changes
+        WHERE abs(sex_ratio_shift) > ?
+        ORDER BY data_year, cc_code, kind",
+        params![
+            GROWTH_SCORE_THRESHOLD,
+            SEX_RATIO_SHIFT_THRESHOLD,
+            SEX_RATIO_SHIFT_THRESHOLD
+        ],
+    )?;
+    let mut stmt = conn.prepare(
+        "SELECT data_year, cc_code, cc_desc, kind, CAST(value AS DOUBLE), CAST(score AS DOUBLE)
+        FROM thai_population_anomalies",
+    )?;
+    let anomalies = stmt
+        .query_map([], |row| {
+            Ok(Anomaly {
+                data_year: row.get(0)?,
+                cc_code: row.get(1)?,
+                cc_desc: row.get(2)?,
+                kind: row.get(3)?,
+                value: row.get(4)?,
+                score: row.get(5)?,
+            })
+        })?
+        .collect();
+    anomalies
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jumps_zeros_and_ratio_shifts_are_flagged() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(
+            "CREATE TABLE thai_population (
+                data_year INTEGER, cc_code INTEGER, cc_desc TEXT,
+                male INTEGER, female INTEGER, total INTEGER
+            )",
+            [],
+        )
+        .unwrap();
+        // Province 10 grows by 1% or 2% a year and jumps by 40% in 2020
+        let mut total = 10_000.0_f64;
+        for year in 2010..=2024 {
+            total *= match year {
+                2020 => 1.4,
+                _ if year % 2 == 0 => 1.02,
+                _ => 1.01,
+            };
+            let total = total.round() as i32;
+            conn.execute(
+                "INSERT INTO thai_population VALUES (?, 10, 'p', ?, ?, ?)",
+                params![year, total / 2, total - total / 2, total],
+            )
+            .unwrap();
+        }
+        conn.execute_batch(
+            "INSERT INTO thai_population VALUES
+                (2022, 38, 'q', 500, 500, 1000),
+                (2023, 38, 'q', 0, 500, 500),
+                (2024, 38, 'q', 600, 500, 1100);",
+        )
+        .unwrap();
+
+        let anomalies = detect_anomalies(&conn).unwrap();
+        let found = anomalies
+            .iter()
+            .map(|anomaly| (anomaly.data_year, anomaly.cc_code, anomaly.kind.as_str()))
+            .collect::<Vec<(i32, i32, &str)>>();
+        assert_eq!(
+            found,
+            vec![
+                (2020, 10, "growth_outlier"),
+                (2023, 38, "sex_ratio_shift"),
+                (2023, 38, "zero_count"),
+                (2024, 38, "sex_ratio_shift"),
+            ]
+        );
+        assert!((anomalies[0].value - 0.4).abs() < 1e-3);
+        assert!(anomalies[0].score > GROWTH_SCORE_THRESHOLD);
+    }
+}
@@ -0,0 +1,188 @@
+use std::fmt;
+use thiserror::Error;
+
+/// Years between the Gregorian calendar and the Buddhist Era used by DOPA.
+pub const BUDDHIST_ERA_OFFSET: i32 = 543;
+/// First Gregorian year published by DOPA.
+pub const FIRST_DATA_YEAR: i32 = 1993;
+/// Last Gregorian year the two-digit Buddhist Era form (2599 BE) can address.
+pub const LAST_DATA_YEAR: i32 = 2599 - BUDDHIST_ERA_OFFSET;
+
+/// Full Thai month names, January first.
+pub const THAI_MONTHS: [&str; 12] = [
+    "มกราคม",
+    "กุมภาพันธ์",
+    "มีนาคม",
+    "เมษายน",
+    "พฤษภาคม",
+    "มิถุนายน",
+    "กรกฎาคม",
+    "สิงหาคม",
+    "กันยายน",
+    "ตุลาคม",
+    "พฤศจิกายน",
+    "ธันวาคม",
+];
+
+/// Abbreviated Thai month names, January first.
+pub const THAI_MONTHS_SHORT: [&str; 12] = [
+    "ม.ค.",
+    "ก.พ.",
+    "มี.ค.",
+    "เม.ย.",
+    "พ.ค.",
+    "มิ.ย.",
+    "ก.ค.",
+    "ส.ค.",
+    "ก.ย.",
+    "ต.ค.",
+    "พ.ย.",
+    "ธ.ค.",
+];
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum CalendarError {
+    #[error("Year {0} is outside of the published years {FIRST_DATA_YEAR}..={LAST_DATA_YEAR}")]
+    YearOutOfRange(i32),
+    #[error("Month {0} is not between 1 and 12")]
+    MonthOutOfRange(u32),
+    #[error("Invalid yymm: {0:?}")]
+    InvalidYymm(String),
+}
+
+/// Converts a Gregorian year to the full Buddhist Era year, e.g. 2023 to 2566.
+pub fn to_buddhist_year(year: i32) -> i32 {
+    year + BUDDHIST_ERA_OFFSET
+}
+
+/// Converts a full Buddhist Era year to the Gregorian year, e.g. 2566 to 2023.
+pub fn to_gregorian_year(buddhist_year: i32) -> i32 {
+    buddhist_year - BUDDHIST_ERA_OFFSET
+}
+
+/// Converts a Gregorian year to the two-digit Buddhist Era year used in the DOPA file names
+/// and `yymm` fields, e.g. 2023 to 66.
+pub fn to_short_buddhist_year(year: i32) -> i32 {
+    to_buddhist_year(year) - 2500
+}
+
+/// Converts a two-digit Buddhist Era year back to the Gregorian year, e.g. 66 to 2023.
+pub fn from_short_buddhist_year(short_year: i32) -> i32 {
+    to_gregorian_year(short_year + 2500)
+}
+
+/// Checks that a Gregorian year has been published by DOPA and can be written in short form.
+pub fn validate_data_year(year: i32) -> Result<i32, CalendarError> {
+    if (FIRST_DATA_YEAR..=LAST_DATA_YEAR).contains(&year) {
+        Ok(year)
+    } else {
+        Err(CalendarError::YearOutOfRange(year))
+    }
+}
+
+/// Maps a month to the Thai fiscal year, which runs from October to September and is named
+/// after the year it ends in.
+pub fn fiscal_year(year: i32, month: u32) -> Result<i32, CalendarError> {
+    match month {
+        1..=9 => Ok(year),
+        10..=12 => Ok(year + 1),
+        _ => Err(CalendarError::MonthOutOfRange(month)),
+    }
+}
+
+/// A month of the statistics, kept with its Gregorian year.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct YearMonth {
+    pub year: i32,
+    pub month: u32,
+}
+
+impl YearMonth {
+    pub fn new(year: i32, month: u32) -> Result<Self, CalendarError> {
+        if !(1..=12).contains(&month) {
+            return Err(CalendarError::MonthOutOfRange(month));
+        }
+        Ok(YearMonth { year, month })
+    }
+
+    /// Parses the `yymm` field of a statistic line, a two-digit Buddhist Era year followed by
+    /// the month, e.g. `"6612"` for December 2023.
+    pub fn parse_yymm(yymm: &str) -> Result<Self, CalendarError> {
+        let yymm = yymm.trim();
+        let invalid = || CalendarError::InvalidYymm(yymm.to_string());
+        if yymm.len() != 4 || !yymm.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        let short_year = yymm[..2].parse::<i32>().map_err(|_| invalid())?;
+        let month = yymm[2..].parse::<u32>().map_err(|_| invalid())?;
+        YearMonth::new(from_short_buddhist_year(short_year), month)
+    }
+
+    /// Formats the month back into the `yymm` form.
+    pub fn to_yymm(&self) -> String {
+        format!("{:02}{:02}", to_short_buddhist_year(self.year), self.month)
+    }
+
+    pub fn buddhist_year(&self) -> i32 {
+        to_buddhist_year(self.year)
+    }
+
+    pub fn fiscal_year(&self) -> i32 {
+        fiscal_year(self.year, self.month).expect("month validated on creation")
+    }
+
+    pub fn thai_month_name(&self) -> &'static str {
+        THAI_MONTHS[self.month as usize - 1]
+    }
+
+    pub fn thai_month_short_name(&self) -> &'static str {
+        THAI_MONTHS_SHORT[self.month as usize - 1]
+    }
+}
+
+impl fmt::Display for YearMonth {
+    /// Writes the month the Thai way, e.g. `ธันวาคม 2566`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.thai_month_name(), self.buddhist_year())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert_to_thai_year() {
+        assert_eq!(to_short_buddhist_year(2000), 43);
+        assert_eq!(to_short_buddhist_year(2023), 66);
+        assert_eq!(to_short_buddhist_year(1993), 36);
+        assert_eq!(to_buddhist_year(2023), 2566);
+        assert_eq!(to_gregorian_year(2566), 2023);
+        assert_eq!(from_short_buddhist_year(36), 1993);
+    }
+
+    #[test]
+    fn test_parse_yymm() {
+        let month = YearMonth::parse_yymm("6612").unwrap();
+        assert_eq!(
+            month,
+            YearMonth {
+                year: 2023,
+                month: 12
+            }
+        );
+        assert_eq!(month.to_yymm(), "6612");
+        assert_eq!(month.fiscal_year(), 2024);
+        assert_eq!(month.thai_month_short_name(), "ธ.ค.");
+        assert_eq!(month.to_string(), "ธันวาคม 2566");
+        assert_eq!(YearMonth::parse_yymm("6609").unwrap().fiscal_year(), 2023);
+
+        assert_eq!(
+            YearMonth::parse_yymm("6613"),
+            Err(CalendarError::MonthOutOfRange(13))
+        );
+        assert!(YearMonth::parse_yymm("2023-12").is_err());
+        assert!(validate_data_year(1992).is_err());
+        assert_eq!(validate_data_year(2025), Ok(2025));
+    }
+}
@@ -0,0 +1,154 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Where the checkpoint of the default ingestion is kept, next to the dataset.
+pub const CHECKPOINT_PATH: &str = "./datasets/checkpoint.json";
+
+/// How far a year went through the pipeline, in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    Fetched,
+    Parsed,
+    Loaded,
+    Exported,
+}
+
+/// The checkpoint entry of a single year.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct YearCheckpoint {
+    pub stage: Stage,
+    pub content_hash: String,
+    pub rows: u64,
+    pub updated_at_unix: u64,
+}
+
+/// Per year progress of the ingestion, persisted as JSON between runs.
+///
+/// Every update is written to disk straight away, so a run killed half way leaves behind
+/// the state of each year at the time it died.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    #[serde(skip)]
+    path: PathBuf,
+    pub years: BTreeMap<i32, YearCheckpoint>,
+}
+
+/// Hashes the raw content of a statistic file.
+///
+/// # Returns
+///
+/// The SHA-256 digest as a lowercase hex string.
+pub fn content_hash(content: &[u8]) -> String {
+    Sha256::digest(content)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+impl Checkpoint {
+    /// Loads the checkpoint stored at `path`, or starts an empty one if there is none yet.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut checkpoint = if path.exists() {
+            let content = fs::read_to_string(path)?;
+            serde_json::from_str::<Checkpoint>(&content)?
+        } else {
+            Checkpoint::default()
+        };
+        checkpoint.path = path.to_path_buf();
+        Ok(checkpoint)
+    }
+
+    /// Writes the checkpoint to a temporary file and renames it over the previous one.
+    pub fn save(&self) -> io::Result<()> {
+        if let Some(parent) = self.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        let temporary = self.path.with_extension("json.tmp");
+        fs::write(&temporary, serde_json::to_string_pretty(self)?)?;
+        fs::rename(temporary, &self.path)
+    }
+
+    /// Tells whether a year can be skipped: it was loaded before from the very same content.
+    pub fn is_up_to_date(&self, year: i32, content_hash: &str) -> bool {
+        self.years
+            .get(&year)
+            .is_some_and(|y| y.stage >= Stage::Loaded && y.content_hash == content_hash)
+    }
+
+    /// Moves a year to `stage` and saves the checkpoint.
+    pub fn advance(
+        &mut self,
+        year: i32,
+        stage: Stage,
+        content_hash: &str,
+        rows: u64,
+    ) -> io::Result<()> {
+        let updated_at_unix = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        self.years.insert(
+            year,
+            YearCheckpoint {
+                stage,
+                content_hash: content_hash.to_string(),
+                rows,
+                updated_at_unix,
+            },
+        );
+        self.save()
+    }
+
+    /// Marks every loaded year as exported and saves the checkpoint.
+    pub fn mark_exported(&mut self) -> io::Result<()> {
+        for year in self.years.values_mut() {
+            if year.stage == Stage::Loaded {
+                year.stage = Stage::Exported;
+            }
+        }
+        self.save()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_content_hash() {
+        assert_eq!(
+            content_hash(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn test_checkpoint_round_trip_and_skip() {
+        let path =
+            env::temp_dir().join(format!("rust_hive_checkpoint_{}.json", std::process::id()));
+        let mut checkpoint = Checkpoint::load(&path).unwrap();
+        assert!(checkpoint.years.is_empty());
+
+        checkpoint.advance(2015, Stage::Parsed, "aaa", 10).unwrap();
+        checkpoint.advance(2016, Stage::Loaded, "bbb", 12).unwrap();
+        // A year which died before being loaded has to be loaded again
+        assert!(!checkpoint.is_up_to_date(2015, "aaa"));
+        assert!(checkpoint.is_up_to_date(2016, "bbb"));
+        assert!(!checkpoint.is_up_to_date(2016, "changed"));
+
+        checkpoint.mark_exported().unwrap();
+        let reloaded = Checkpoint::load(&path).unwrap();
+        assert_eq!(reloaded.years[&2015].stage, Stage::Parsed);
+        assert_eq!(reloaded.years[&2016].stage, Stage::Exported);
+        assert!(reloaded.is_up_to_date(2016, "bbb"));
+
+        fs::remove_file(path).unwrap();
+    }
+}
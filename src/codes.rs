@@ -0,0 +1,300 @@
+use duckdb::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum CodeError {
+    #[error("Invalid {kind} code {code:?}: only digits are allowed")]
+    NotDigits { kind: &'static str, code: String },
+    #[error("Invalid {kind} code {code:?}: longer than {width} digits")]
+    TooLong {
+        kind: &'static str,
+        code: String,
+        width: usize,
+    },
+}
+
+/// Checks that `code` only holds digits and pads it with zeros to `width`.
+///
+/// Blank codes are read as all zeros, which is how the files leave out a level.
+fn normalise(kind: &'static str, code: &str, width: usize) -> Result<String, CodeError> {
+    let code = code.trim();
+    if !code.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(CodeError::NotDigits {
+            kind,
+            code: code.to_string(),
+        });
+    }
+    // Extra leading zeros, as in "001", do not make a code longer
+    let significant = code.trim_start_matches('0');
+    if significant.len() > width {
+        return Err(CodeError::TooLong {
+            kind,
+            code: code.to_string(),
+            width,
+        });
+    }
+    Ok(format!("{:0>width$}", significant, width = width))
+}
+
+/// Province code (`cc_code`), two digits. `00` stands for the whole country.
+///
+/// The warehouse keeps it as an INTEGER, so it is bound and read as a number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct ProvinceCode(u8);
+
+impl ProvinceCode {
+    pub const WIDTH: usize = 2;
+
+    pub fn new(value: u8) -> Result<Self, CodeError> {
+        value.to_string().parse()
+    }
+
+    pub fn value(&self) -> i32 {
+        self.0 as i32
+    }
+
+    /// Tells whether the line is the national total rather than a province.
+    pub fn is_country(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Tells whether `district` lies in this province.
+    pub fn is_parent_of(&self, district: &DistrictCode) -> bool {
+        district.province() == *self
+    }
+}
+
+impl FromStr for ProvinceCode {
+    type Err = CodeError;
+
+    fn from_str(code: &str) -> Result<Self, Self::Err> {
+        let code = normalise("province", code, Self::WIDTH)?;
+        Ok(ProvinceCode(code.parse().expect("two digits fit in a u8")))
+    }
+}
+
+impl fmt::Display for ProvinceCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02}", self.0)
+    }
+}
+
+impl ToSql for ProvinceCode {
+    fn to_sql(&self) -> duckdb::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.value()))
+    }
+}
+
+impl FromSql for ProvinceCode {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value {
+            ValueRef::Text(_) => String::column_result(value)?.parse(),
+            _ => i64::column_result(value)?.to_string().parse(),
+        }
+        .map_err(|e| FromSqlError::Other(Box::new(e)))
+    }
+}
+
+impl Serialize for ProvinceCode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ProvinceCode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(D::Error::custom)
+    }
+}
+
+/// Declares a code kept as a zero-padded string of `width` digits, bound as TEXT in DuckDB.
+macro_rules! text_code {
+    ($(#[$meta:meta])* $name:ident, $kind:literal, $width:literal) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        pub struct $name(String);
+
+        impl $name {
+            pub const WIDTH: usize = $width;
+
+            pub fn as_str(&self) -> &str {
+                &self.0
+            }
+
+            /// Tells whether the code is the all-zeros placeholder of a level left out.
+            pub fn is_placeholder(&self) -> bool {
+                self.0.bytes().all(|b| b == b'0')
+            }
+
+            /// The province the code belongs to, from its first two digits.
+            pub fn province(&self) -> ProvinceCode {
+                self.0[..ProvinceCode::WIDTH]
+                    .parse()
+                    .expect("codes start with two digits")
+            }
+        }
+
+        impl Default for $name {
+            fn default() -> Self {
+                $name("0".repeat($width))
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = CodeError;
+
+            fn from_str(code: &str) -> Result<Self, Self::Err> {
+                Ok($name(normalise($kind, code, $width)?))
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(&self.0)
+            }
+        }
+
+        impl ToSql for $name {
+            fn to_sql(&self) -> duckdb::Result<ToSqlOutput<'_>> {
+                Ok(ToSqlOutput::from(self.as_str()))
+            }
+        }
+
+        impl FromSql for $name {
+            fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+                String::column_result(value)?
+                    .parse()
+                    .map_err(|e| FromSqlError::Other(Box::new(e)))
+            }
+        }
+
+        impl Serialize for $name {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_str(self)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                String::deserialize(deserializer)?
+                    .parse()
+                    .map_err(D::Error::custom)
+            }
+        }
+    };
+}
+
+text_code!(
+    /// Registration office code (`rcode_code`), four digits starting with the province.
+    RegistrationOfficeCode,
+    "registration office",
+    4
+);
+
+text_code!(
+    /// District code (`ccaatt_code`), six digits: province, district, then `00`.
+    DistrictCode,
+    "district",
+    6
+);
+
+text_code!(
+    /// Subdistrict code (`ccaattmm_code`), eight digits starting with its district code.
+    SubdistrictCode,
+    "subdistrict",
+    8
+);
+
+impl DistrictCode {
+    /// Tells whether `subdistrict` lies in this district.
+    pub fn is_parent_of(&self, subdistrict: &SubdistrictCode) -> bool {
+        subdistrict.district() == *self
+    }
+}
+
+impl SubdistrictCode {
+    /// The district the subdistrict belongs to, from its first six digits.
+    pub fn district(&self) -> DistrictCode {
+        DistrictCode(self.0[..DistrictCode::WIDTH].to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use duckdb::Connection;
+
+    #[test]
+    fn test_parse_pads_and_validates() {
+        assert_eq!("1".parse::<ProvinceCode>().unwrap().to_string(), "01");
+        assert_eq!("001".parse::<ProvinceCode>().unwrap().value(), 1);
+        assert!("0".parse::<ProvinceCode>().unwrap().is_country());
+        assert!(matches!(
+            "100".parse::<ProvinceCode>(),
+            Err(CodeError::TooLong { .. })
+        ));
+        assert!(matches!(
+            "RC01".parse::<RegistrationOfficeCode>(),
+            Err(CodeError::NotDigits { .. })
+        ));
+        assert_eq!(
+            "0".parse::<DistrictCode>().unwrap(),
+            DistrictCode::default()
+        );
+        assert!(" ".parse::<SubdistrictCode>().unwrap().is_placeholder());
+    }
+
+    #[test]
+    fn test_parent_and_child() {
+        let subdistrict: SubdistrictCode = "38010101".parse().unwrap();
+        let district = subdistrict.district();
+        assert_eq!(district.as_str(), "380101");
+        assert!(district.is_parent_of(&subdistrict));
+        assert!(ProvinceCode::new(38).unwrap().is_parent_of(&district));
+        assert_eq!(subdistrict.province().to_string(), "38");
+        assert_eq!(
+            "3801"
+                .parse::<RegistrationOfficeCode>()
+                .unwrap()
+                .province()
+                .value(),
+            38
+        );
+    }
+
+    #[test]
+    fn test_serde_uses_padded_codes() {
+        let district: DistrictCode = "50100".parse().unwrap();
+        assert_eq!(serde_json::to_string(&district).unwrap(), "\"050100\"");
+        let province: ProvinceCode = serde_json::from_str("\"5\"").unwrap();
+        assert_eq!(province.value(), 5);
+        assert!(serde_json::from_str::<ProvinceCode>("\"x\"").is_err());
+    }
+
+    #[test]
+    fn test_duckdb_round_trip() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("CREATE TABLE codes (cc_code INTEGER, ccaatt_code TEXT)", [])
+            .unwrap();
+        let province = ProvinceCode::new(5).unwrap();
+        let district: DistrictCode = "50100".parse().unwrap();
+        conn.execute(
+            "INSERT INTO codes VALUES (?, ?)",
+            duckdb::params![province, district],
+        )
+        .unwrap();
+        let read: (ProvinceCode, DistrictCode) = conn
+            .query_row("SELECT cc_code, ccaatt_code FROM codes", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!(read, (province, district));
+        assert_eq!(read.1.as_str(), "050100");
+    }
+}
@@ -0,0 +1,262 @@
+use duckdb::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Where the crosswalk of the province boundary changes is read from by default.
+pub const CROSSWALK_PATH: &str = "./crosswalk.json";
+
+fn full_weight() -> f64 {
+    1.0
+}
+
+/// A single step of a boundary change between two province codes.
+///
+/// A split is written as one mapping per new province from the old code, a merge as one
+/// mapping per old province to the new code.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CrosswalkMapping {
+    /// The first year the new geography is used in the files.
+    pub effective_year: i32,
+    pub from_code: i32,
+    pub to_code: i32,
+    /// Share of the population of `from_code` which moved to `to_code`.
+    #[serde(default = "full_weight")]
+    pub weight: f64,
+    /// Share of the population of `to_code` which came from `from_code`, used to go back to
+    /// the geography before the change.
+    #[serde(default = "full_weight")]
+    pub reverse_weight: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+/// Configurable split and merge mappings between province codes.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Crosswalk {
+    pub mappings: Vec<CrosswalkMapping>,
+}
+
+impl Crosswalk {
+    /// Loads the crosswalk stored at `path`, or an empty one if there is none.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        if !path.exists() {
+            return Ok(Crosswalk::default());
+        }
+        let content = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    /// Maps a code of `data_year` onto the geography of `reference_year`.
+    ///
+    /// Every boundary change between the two years is applied in turn, forwards when the
+    /// reference year is later and backwards otherwise. Codes untouched by the changes map to
+    /// themselves.
+    ///
+    /// # Returns
+    ///
+    /// The codes of the reference geography with the share of the population going to each,
+    /// ordered by code.
+    pub fn resolve(&self, code: i32, data_year: i32, reference_year: i32) -> Vec<(i32, f64)> {
+        let mut shares = BTreeMap::from([(code, 1.0)]);
+        let mut effective_years = self
+            .mappings
+            .iter()
+            .map(|m| m.effective_year)
+            .filter(|&year| {
+                (data_year < year && year <= reference_year)
+                    || (reference_year < year && year <= data_year)
+            })
+            .collect::<Vec<i32>>();
+        effective_years.sort_unstable();
+        effective_years.dedup();
+        let forward = data_year < reference_year;
+        if !forward {
+            effective_years.reverse();
+        }
+
+        for year in effective_years {
+            let mut next = BTreeMap::new();
+            for (current, share) in shares {
+                let targets = self
+                    .mappings
+                    .iter()
+                    .filter(|m| m.effective_year == year)
+                    .filter_map(|m| match forward {
+                        true if m.from_code == current => Some((m.to_code, m.weight)),
+                        false if m.to_code == current => Some((m.from_code, m.reverse_weight)),
+                        _ => None,
+                    })
+                    .collect::<Vec<(i32, f64)>>();
+                if targets.is_empty() {
+                    *next.entry(current).or_insert(0.0) += share;
+                }
+                for (target, weight) in targets {
+                    *next.entry(target).or_insert(0.0) += share * weight;
+                }
+            }
+            shares = next;
+        }
+        shares.into_iter().collect()
+    }
+}
+
+/// Re-aggregates `thai_population` onto the province geography of `reference_year`.
+///
+/// The weights of every code touched by the crosswalk are written to
+/// `thai_population_crosswalk`, and the view `thai_population_harmonised` sums the counts of
+/// each year onto the reference codes, named after `dim_admin_area` as of the reference year.
+/// Counts of split provinces are estimates and kept as doubles.
+///
+/// # Arguments
+///
+/// * `conn` - A reference to a DuckDB Connection holding 'thai_population' and 'dim_admin_area'.
+/// * `crosswalk` - The boundary changes to apply.
+/// * `reference_year` - The Gregorian year whose geography every year is mapped onto.
+///
+/// # Returns
+///
+/// * `duckdb::Result<usize>` - The number of crosswalk weights written.
+///
+pub fn build_harmonised_view(
+    conn: &Connection,
+    crosswalk: &Crosswalk,
+    reference_year: i32,
+) -> duckdb::Result<usize> {
+    conn.execute(
+        "CREATE OR REPLACE TABLE thai_population_crosswalk (
+            data_year INTEGER,
+            cc_code INTEGER,
+            reference_code INTEGER,
+            weight DOUBLE
+        )",
+        [],
+    )?;
+
+    let codes = conn
+        .prepare(
+            "SELECT DISTINCT data_year, cc_code FROM thai_population WHERE cc_code IS NOT NULL",
+        )?
+        .query_map([], |row| Ok((row.get::<_, i32>(0)?, row.get::<_, i32>(1)?)))?
+        .collect::<duckdb::Result<Vec<(i32, i32)>>>()?;
+    let mut insert = conn.prepare("INSERT INTO thai_population_crosswalk VALUES (?, ?, ?, ?)")?;
+    let mut written = 0;
+    for (data_year, code) in codes {
+        let shares = crosswalk.resolve(code, data_year, reference_year);
+        if shares == [(code, 1.0)] {
+            continue;
+        }
+        for (reference_code, weight) in shares {
+            written += insert.execute(params![data_year, code, reference_code, weight])?;
+        }
+    }
+
+    conn.execute(
+        &format!(
+            "CREATE OR REPLACE VIEW thai_population_harmonised AS
+            WITH mapped AS (
+                SELECT
+                    p.data_year,
+                    coalesce(c.reference_code, p.cc_code) AS cc_code,
+                    sum(p.male * coalesce(c.weight, 1)) AS male,
+                    sum(p.female * coalesce(c.weight, 1)) AS female,
+                    sum(p.total * coalesce(c.weight, 1)) AS total,
+                    sum(p.house * coalesce(c.weight, 1)) AS house
+                FROM thai_population p
+                LEFT JOIN thai_population_crosswalk c
+                    ON c.data_year = p.data_year AND c.cc_code = p.cc_code
+                GROUP BY ALL
+            )
+            SELECT
+                mapped.data_year,
+                {reference_year} AS reference_year,
+                mapped.cc_code,
+                d.name AS cc_desc,
+                mapped.male,
+                mapped.female,
+                mapped.total,
+                mapped.house
+            FROM mapped
+            LEFT JOIN dim_admin_area d
+                ON d.level = 'province'
+                AND d.code = CAST(mapped.cc_code AS TEXT)
+                AND d.valid_from <= {reference_year}
+                AND coalesce(d.valid_to, {reference_year}) >= {reference_year}",
+            reference_year = reference_year
+        ),
+        [],
+    )?;
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geography::build_dim_admin_area;
+
+    fn bueng_kan_split() -> Crosswalk {
+        serde_json::from_str(
+            r#"{"mappings": [
+                {"effective_year": 2011, "from_code": 43, "to_code": 43, "weight": 0.6},
+                {"effective_year": 2011, "from_code": 43, "to_code": 38, "weight": 0.4}
+            ]}"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_resolve_split_both_ways() {
+        let crosswalk = bueng_kan_split();
+        assert_eq!(
+            crosswalk.resolve(43, 2010, 2023),
+            vec![(38, 0.4), (43, 0.6)]
+        );
+        assert_eq!(crosswalk.resolve(43, 2011, 2023), vec![(43, 1.0)]);
+        assert_eq!(crosswalk.resolve(38, 2020, 2000), vec![(43, 1.0)]);
+        assert_eq!(crosswalk.resolve(10, 1993, 2023), vec![(10, 1.0)]);
+    }
+
+    #[test]
+    fn test_harmonised_view_reaggregates_onto_reference_year() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE thai_population (
+                data_year INTEGER, cc_code INTEGER, cc_desc TEXT, ccaatt_code TEXT,
+                ccaatt_desc TEXT, male INTEGER, female INTEGER, total INTEGER, house INTEGER
+            );
+            INSERT INTO thai_population VALUES
+                (2010, 43, 'Nong Khai', '', '', 500, 500, 1000, 300),
+                (2011, 43, 'Nong Khai', '', '', 300, 300, 600, 180),
+                (2011, 38, 'Bueng Kan', '', '', 200, 200, 400, 120);",
+        )
+        .unwrap();
+        build_dim_admin_area(&conn).unwrap();
+
+        assert_eq!(
+            build_harmonised_view(&conn, &bueng_kan_split(), 2011).unwrap(),
+            2
+        );
+        let bueng_kan: (f64, String) = conn
+            .query_row(
+                "SELECT total, cc_desc FROM thai_population_harmonised
+                WHERE data_year = 2010 AND cc_code = 38",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(bueng_kan, (400.0, "Bueng Kan".to_string()));
+
+        build_harmonised_view(&conn, &bueng_kan_split(), 2010).unwrap();
+        let nong_khai: f64 = conn
+            .query_row(
+                "SELECT total FROM thai_population_harmonised
+                WHERE data_year = 2011 AND cc_code = 43",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(nong_khai, 1000.0);
+    }
+}
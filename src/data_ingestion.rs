@@ -1,28 +1,14 @@
 mod databases;
 use databases::duckdb_functions::{
-    create_duck_db_table, generate_insert_sql, query_population_all, write_into_hive_partition,
+    create_duck_db_table, generate_insert_sql, write_into_hive_partition,
 };
 use duckdb::{Connection, Result};
+use rust_hive::calendar::to_short_buddhist_year;
+use rust_hive::query::PopulationQuery;
 use std::error::Error;
 
-/// Converts a Gregorian year to a Thai year.
-///3
-/// This function takes an integer representing a Gregorian year and returns the corresponding Thai year.
-/// The conversion is based on the Thai calendar system, which starts from the year 2500.
-///
-/// # Parameters
-///
-/// * `year` - An integer representing the Gregorian year.
-///
-/// # Returns
-///
-/// An integer representing the corresponding Thai year.
-fn convert_to_thai_year(year: i32) -> i32 {
-    year + 543 - 2500
-}
-
 fn get_data_stat_by_year(year: i32) -> Result<String, Box<dyn Error>> {
-    let thai_year = convert_to_thai_year(year);
+    let thai_year = to_short_buddhist_year(year);
     let url = format!(
         "https://stat.bora.dopa.go.th/new_stat/file/{}/stat_c{}.txt",
         thai_year, thai_year
@@ -91,7 +77,22 @@ fn main() -> Result<(), Box<dyn Error>> {
         }
         year += 1;
     }
-    query_population_all(&conn)?;
+    let query = PopulationQuery::new();
+    let mut stmt = query.prepare(&conn)?;
+    for record in query.rows(&mut stmt)? {
+        let record = record?;
+        println!(
+            "Year: {}, YYMM: {}, Code: {}, Desc: {}, Male: {}, Female: {}, Total: {}, House: {}",
+            record.data_year,
+            record.row.yymm,
+            record.row.cc_code,
+            record.row.cc_desc,
+            record.row.male,
+            record.row.female,
+            record.row.total,
+            record.row.house
+        );
+    }
     write_into_hive_partition(&conn)?;
     Ok(())
 }
@@ -1,11 +1,39 @@
 #![allow(dead_code)]
 #![allow(clippy::too_many_arguments)]
 
-use duckdb::{Connection, Result};
+use duckdb::{params_from_iter, Connection, Result, ToSql};
+use rust_hive::metrics::metrics;
+use rust_hive::migrations::Migration;
 use rust_hive::parsers::population::PopulationRow;
+use rust_hive::schema::RowSchema;
 use std::fs;
 use std::io::Error;
 use std::path::Path;
+use std::time::Instant;
+use tracing::{info, instrument};
+
+/// Where the persistent DuckDB warehouse is stored.
+pub const WAREHOUSE_PATH: &str = "./datasets/thai_population.duckdb";
+
+/// Root directory of the Hive partitioned export.
+pub const HIVE_DATASET_PATH: &str = "./datasets/thai_population";
+
+/// Root directory of the Hive partitioned export of `thai_population_indicators`.
+pub const INDICATORS_DATASET_PATH: &str = "./datasets/thai_population_indicators";
+
+/// Column definitions of the 'thai_population' table: the year of the file, then the
+/// columns of `PopulationRow`.
+fn thai_population_columns() -> String {
+    format!(
+        "data_year INTEGER,\n{},\nPRIMARY KEY (data_year, cc_code)",
+        PopulationRow::column_definitions()
+    )
+}
+
+/// Columns selected into the Hive export, `data_year` being the partition column.
+fn thai_population_export_columns() -> String {
+    format!("data_year, {}", PopulationRow::column_list())
+}
 
 /// Creates or replaces a table named 'thai_population' in the DuckDB database.
 ///
@@ -22,28 +50,274 @@ use std::path::Path;
 ///
 pub fn create_duck_db_table(conn: &Connection) -> Result<()> {
     conn.execute(
-        "CREATE OR REPLACE TABLE thai_population (
+        &format!(
+            "CREATE OR REPLACE TABLE thai_population ({});",
+            thai_population_columns()
+        ),
+        [],
+    )?;
+    Ok(())
+}
+
+/// Creates the 'thai_population' table only if it does not exist yet.
+///
+/// Unlike `create_duck_db_table`, this keeps the rows loaded by previous runs of a
+/// persistent database.
+///
+/// # Arguments
+///
+/// * `conn` - A reference to a DuckDB Connection object used to execute the SQL statement.
+///
+/// # Returns
+///
+/// * `Result<()>` - Returns Ok(()) if the table exists afterwards, or an error if the operation fails.
+///
+pub fn ensure_duck_db_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        &format!(
+            "CREATE TABLE IF NOT EXISTS thai_population ({});",
+            thai_population_columns()
+        ),
+        [],
+    )?;
+    Ok(())
+}
+
+/// Opens the persistent DuckDB warehouse stored next to the dataset.
+///
+/// # Returns
+///
+/// * `Result<Connection>` - The connection to `./datasets/thai_population.duckdb`.
+///
+pub fn open_warehouse() -> Result<Connection> {
+    let _ = prepare_directory();
+    Connection::open(WAREHOUSE_PATH)
+}
+
+/// Creates the 'thai_population_sources' table, remembering which source file each year was loaded from.
+///
+/// # Arguments
+///
+/// * `conn` - A reference to a DuckDB Connection object used to execute the SQL statement.
+///
+/// # Returns
+///
+/// * `Result<()>` - Returns Ok(()) if the table exists afterwards, or an error if the operation fails.
+///
+pub fn ensure_source_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS thai_population_sources (
+            data_year INTEGER PRIMARY KEY,
+            content_hash TEXT,
+            row_count BIGINT,
+            loaded_at TIMESTAMP
+        );",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Returns the content hash of the source file a year was last loaded from.
+///
+/// # Arguments
+///
+/// * `conn` - A reference to a DuckDB Connection object.
+/// * `data_year` - The Gregorian year to look up.
+///
+/// # Returns
+///
+/// * `Result<Option<String>>` - The stored hash, or `None` if the year was never loaded.
+///
+pub fn stored_content_hash(conn: &Connection, data_year: i32) -> Result<Option<String>> {
+    let mut stmt =
+        conn.prepare("SELECT content_hash FROM thai_population_sources WHERE data_year = ?")?;
+    let mut rows = stmt.query([data_year])?;
+    match rows.next()? {
+        Some(row) => Ok(Some(row.get(0)?)),
+        None => Ok(None),
+    }
+}
+
+/// Records the source file a year has just been loaded from.
+///
+/// # Arguments
+///
+/// * `conn` - A reference to a DuckDB Connection object.
+/// * `data_year` - The Gregorian year which was loaded.
+/// * `content_hash` - The hash of the source file.
+/// * `row_count` - The number of rows loaded.
+///
+/// # Returns
+///
+/// * `Result<()>` - Returns Ok(()) if the source was recorded.
+///
+pub fn record_source(
+    conn: &Connection,
+    data_year: i32,
+    content_hash: &str,
+    row_count: u64,
+) -> Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO thai_population_sources VALUES (?, ?, ?, current_timestamp)",
+        duckdb::params![data_year, content_hash, row_count],
+    )?;
+    Ok(())
+}
+
+/// Creates the 'thai_population_changes' audit table of the revisions made by DOPA.
+///
+/// Each row describes a `(data_year, cc_code)` which was inserted, deleted or changed when a
+/// year was loaded again, with the old and new counts.
+///
+/// # Arguments
+///
+/// * `conn` - A reference to a DuckDB Connection object used to execute the SQL statement.
+///
+/// # Returns
+///
+/// * `Result<()>` - Returns Ok(()) if the table exists afterwards, or an error if the operation fails.
+///
+pub fn ensure_changes_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS thai_population_changes (
+            detected_at TIMESTAMP,
             data_year INTEGER,
-            yymm TEXT,
             cc_code INTEGER,
-            cc_desc TEXT,
-            rcode_code TEXT,
-            rcode_desc TEXT,
-            ccaatt_code TEXT,
-            ccaatt_desc TEXT,
-            ccaattmm_code TEXT,
-            ccaattmm_desc TEXT,
-            male INTEGER,
-            female INTEGER,
-            total INTEGER,
-            house INTEGER,
-            PRIMARY KEY (data_year, cc_code)
+            change_type TEXT,
+            old_male INTEGER,
+            new_male INTEGER,
+            old_female INTEGER,
+            new_female INTEGER,
+            old_total INTEGER,
+            new_total INTEGER,
+            old_house INTEGER,
+            new_house INTEGER
         );",
         [],
     )?;
     Ok(())
 }
 
+/// Migrations of the warehouse schema, applied in order on startup.
+///
+/// Append a step here for any change to the tables instead of editing a previous one, so
+/// warehouses created by older runs keep their rows.
+pub const WAREHOUSE_MIGRATIONS: [Migration; 3] = [
+    Migration {
+        version: 1,
+        description: "Create thai_population",
+        apply: ensure_duck_db_table,
+    },
+    Migration {
+        version: 2,
+        description: "Create thai_population_sources",
+        apply: ensure_source_table,
+    },
+    Migration {
+        version: 3,
+        description: "Create thai_population_changes",
+        apply: ensure_changes_table,
+    },
+];
+
+/// Copies the stored rows of a year into a temporary table before the year is loaded again.
+///
+/// # Arguments
+///
+/// * `conn` - A reference to a DuckDB Connection object.
+/// * `data_year` - The Gregorian year to snapshot.
+///
+/// # Returns
+///
+/// * `Result<usize>` - The number of rows in the snapshot.
+///
+pub fn snapshot_year(conn: &Connection, data_year: i32) -> Result<usize> {
+    conn.execute(
+        &format!(
+            "CREATE OR REPLACE TEMP TABLE previous_year_snapshot AS
+            SELECT * FROM thai_population WHERE data_year = {}",
+            data_year
+        ),
+        [],
+    )?;
+    conn.query_row("SELECT count(*) FROM previous_year_snapshot", [], |row| {
+        row.get(0)
+    })
+}
+
+/// Diffs the snapshot taken by `snapshot_year` against the freshly loaded rows of the year.
+///
+/// Rows are matched by `cc_code`; a match whose `male`, `female`, `total` or `house` differ is
+/// recorded as changed. The snapshot is dropped afterwards.
+///
+/// # Arguments
+///
+/// * `conn` - A reference to a DuckDB Connection object.
+/// * `data_year` - The Gregorian year which was loaded again.
+///
+/// # Returns
+///
+/// * `Result<usize>` - The number of changes recorded into 'thai_population_changes'.
+///
+pub fn record_year_changes(conn: &Connection, data_year: i32) -> Result<usize> {
+    let changes = conn.execute(
+        &format!(
+            "INSERT INTO thai_population_changes
+            SELECT
+                current_timestamp,
+                {year},
+                COALESCE(new.cc_code, old.cc_code),
+                CASE
+                    WHEN old.cc_code IS NULL THEN 'inserted'
+                    WHEN new.cc_code IS NULL THEN 'deleted'
+                    ELSE 'changed'
+                END,
+                old.male, new.male,
+                old.female, new.female,
+                old.total, new.total,
+                old.house, new.house
+            FROM previous_year_snapshot AS old
+            FULL OUTER JOIN (
+                SELECT * FROM thai_population WHERE data_year = {year}
+            ) AS new ON old.cc_code = new.cc_code
+            WHERE old.cc_code IS NULL
+                OR new.cc_code IS NULL
+                OR old.male IS DISTINCT FROM new.male
+                OR old.female IS DISTINCT FROM new.female
+                OR old.total IS DISTINCT FROM new.total
+                OR old.house IS DISTINCT FROM new.house",
+            year = data_year
+        ),
+        [],
+    )?;
+    drop_year_snapshot(conn)?;
+    Ok(changes)
+}
+
+/// Drops the snapshot taken by `snapshot_year`.
+pub fn drop_year_snapshot(conn: &Connection) -> Result<()> {
+    conn.execute("DROP TABLE IF EXISTS previous_year_snapshot", [])?;
+    Ok(())
+}
+
+/// Deletes every row of a given data year.
+///
+/// # Arguments
+///
+/// * `conn` - A reference to a DuckDB Connection object.
+/// * `data_year` - The Gregorian year whose rows are deleted.
+///
+/// # Returns
+///
+/// * `Result<usize>` - The number of deleted rows.
+///
+pub fn delete_year(conn: &Connection, data_year: i32) -> Result<usize> {
+    conn.execute(
+        "DELETE FROM thai_population WHERE data_year = ?",
+        [data_year],
+    )
+}
+
 /// Generate Insertion SQL statement.
 ///
 /// This function generates a SQL statement to insert a population row
@@ -93,38 +367,31 @@ pub fn generate_insert_sql(
 }
 
 
-/// The function generates an SQL insert statement using data from a PopulationRow struct for a given
-/// data year.
-/// 
-/// Arguments:
-/// 
-/// * `data_year`: The `data_year` parameter is an integer representing the year for which the data is
-/// being inserted into the database.
-/// * `row`: The `generate_insert_sql_given_row_struct` function takes in the `data_year` as an `i32`
-/// and a reference to a `PopulationRow` struct named `row`. The `PopulationRow` struct likely contains
-/// the following fields:
-/// 
-/// Returns:
-/// 
-/// A SQL insert statement is being returned as a String, generated based on the provided data_year and
-/// PopulationRow struct fields.
-pub fn generate_insert_sql_given_row_struct(data_year: i32, row: &PopulationRow) -> String {
-    generate_insert_sql(
-        data_year,
-        &row.yymm,
-        row.cc_code,
-        &row.cc_desc,
-        &row.rcode_code,
-        &row.rcode_desc,
-        &row.ccaatt_code,
-        &row.ccaatt_desc,
-        &row.ccaattmm_code,
-        &row.ccaattmm_desc,
-        row.male,
-        row.female,
-        row.total,
-        row.house,
-    )
+/// Inserts a population row of a given year, binding every column of `PopulationRow`.
+///
+/// # Arguments
+///
+/// * `conn` - A reference to a DuckDB Connection holding the 'thai_population' table.
+/// * `data_year` - The Gregorian year of the file the row was read from.
+/// * `row` - The row to insert.
+///
+/// # Returns
+///
+/// * `Result<usize>` - The number of inserted rows.
+///
+pub fn insert_population_row(
+    conn: &Connection,
+    data_year: i32,
+    row: &PopulationRow,
+) -> Result<usize> {
+    let mut params: Vec<&dyn ToSql> = vec![&data_year];
+    params.extend(row.to_params());
+    conn.prepare_cached(&format!(
+        "INSERT INTO thai_population (data_year, {}) VALUES (?, {})",
+        PopulationRow::column_list(),
+        PopulationRow::placeholders()
+    ))?
+    .execute(params_from_iter(params))
 }
 
 /// The function `prepare_directory` creates a directory named "datasets" if it does not already exist.
@@ -145,18 +412,86 @@ fn prepare_directory() -> Result<(), Error> {
 /// Arguments:
 /// 
 /// * `conn`: The `conn` parameter in the `write_into_hive_partition` function is of type `&Connection`,
-/// which likely represents a connection to a database or data storage system. This connection is used
-/// to execute a SQL query to copy data into a Hive partition.
+///   which likely represents a connection to a database or data storage system. This connection is used
+///   to execute a SQL query to copy data into a Hive partition.
 /// 
 /// Returns:
 /// 
 /// The `write_into_hive_partition` function is returning a `Result` with a unit type `()` as the
 /// success value.
+#[instrument(name = "export", skip(conn))]
 pub fn write_into_hive_partition(conn: &Connection) -> Result<()> {
+    copy_into_hive_partition(
+        conn,
+        &format!(
+            "(SELECT {} FROM thai_population)",
+            thai_population_export_columns()
+        ),
+        HIVE_DATASET_PATH,
+    )
+}
+
+/// Writes `thai_population_indicators` into its own Hive partitioned dataset.
+///
+/// Every partition is rewritten, growth and shares depending on the neighbouring years.
+#[instrument(name = "export", skip(conn))]
+pub fn write_indicators_into_hive_partition(conn: &Connection) -> Result<()> {
+    copy_into_hive_partition(conn, "thai_population_indicators", INDICATORS_DATASET_PATH)
+}
+
+/// Rewrites the Hive partitions of the given years only, leaving the other partitions untouched.
+///
+/// The `data_year=` directories of the years are removed first, so no file of a previous
+/// export survives next to the new one.
+///
+/// # Arguments
+///
+/// * `conn` - A reference to a DuckDB Connection object.
+/// * `years` - The Gregorian years whose partitions are rewritten.
+///
+/// # Returns
+///
+/// * `Result<(), Error>` - Returns Ok(()) once every partition has been rewritten.
+///
+#[instrument(name = "export", skip(conn))]
+pub fn write_years_into_hive_partition(
+    conn: &Connection,
+    years: &[i32],
+) -> std::result::Result<(), Error> {
+    if years.is_empty() {
+        return Ok(());
+    }
+    for year in years {
+        let partition = Path::new(HIVE_DATASET_PATH).join(format!("data_year={}", year));
+        if partition.exists() {
+            fs::remove_dir_all(partition)?;
+        }
+    }
+    let year_list = years
+        .iter()
+        .map(|year| year.to_string())
+        .collect::<Vec<String>>()
+        .join(", ");
+    copy_into_hive_partition(
+        conn,
+        &format!(
+            "(SELECT {} FROM thai_population WHERE data_year IN ({}))",
+            thai_population_export_columns(),
+            year_list
+        ),
+        HIVE_DATASET_PATH,
+    )
+    .map_err(Error::other)
+}
+
+/// Copies a table or a parenthesised query into the Hive partitioned dataset at `destination`.
+fn copy_into_hive_partition(conn: &Connection, source: &str, destination: &str) -> Result<()> {
+    let started = Instant::now();
     let _ = prepare_directory();
     conn.execute(
-        "
-        COPY thai_population TO './datasets/thai_population' (
+        &format!(
+            "
+        COPY {} TO '{}' (
             FORMAT PARQUET,
             PARTITION_BY (data_year),
             OVERWRITE_OR_IGNORE,
@@ -164,44 +499,11 @@ pub fn write_into_hive_partition(conn: &Connection) -> Result<()> {
             FILE_EXTENSION 'parquet.gz'
         );
         ",
+            source, destination
+        ),
         [],
     )?;
-    Ok(())
-}
-
-/// The function `query_population_all` retrieves and prints population data from a database table in
-/// Rust.
-/// 
-/// Arguments:
-/// 
-/// * `conn`: The `conn` parameter in the `query_population_all` function is a reference to a database
-/// connection. This connection is used to interact with the database and execute SQL queries.
-/// 
-/// Returns:
-/// 
-/// The `query_population_all` function is querying the `thai_population` table from the database using
-/// the provided connection. It retrieves data for each row in the table, extracts specific columns'
-/// values, and prints them out in a formatted way. The function returns a `Result` indicating success
-/// or an error.
-pub fn query_population_all(conn: &Connection) -> Result<()> {
-    let mut stmt = conn.prepare("SELECT * FROM thai_population;")?;
-    let mut rows = stmt.query([])?;
-
-    while let Some(row) = rows.next()? {
-        let data_year: i32 = row.get(0)?; // Access columns by index
-        let yymm: String = row.get(1)?;
-        let cc_code: i32 = row.get(2)?;
-        let cc_desc: String = row.get(3)?;
-        let male: i32 = row.get(10)?;
-        let female: i32 = row.get(11)?;
-        let total: i32 = row.get(12)?;
-        let house: i32 = row.get(13)?;
-
-        println!(
-            "Year: {}, YYMM: {}, Code: {}, Desc: {}, Male: {}, Female: {}, Total: {}, House: {}",
-            data_year, yymm, cc_code, cc_desc, male, female, total, house
-        );
-    }
-
+    metrics().observe_export(started.elapsed());
+    info!(elapsed_ms = started.elapsed().as_millis() as u64, "Exported Hive partitions");
     Ok(())
 }
@@ -0,0 +1,345 @@
+use crate::codes::ProvinceCode;
+use duckdb::{params, Connection, Result, Row};
+
+/// Level of an administrative area in the DOPA files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdminLevel {
+    /// Identified by `cc_code`/`cc_desc`.
+    Province,
+    /// Identified by `ccaatt_code`/`ccaatt_desc`, its parent being the `cc_code` of the row.
+    /// Province lines hold zeros there and are left out.
+    District,
+}
+
+impl AdminLevel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AdminLevel::Province => "province",
+            AdminLevel::District => "district",
+        }
+    }
+}
+
+/// Region of a province, read from the first digit of its DOPA code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Region {
+    Central,
+    East,
+    Northeast,
+    North,
+    West,
+    South,
+}
+
+impl Region {
+    pub const ALL: [Region; 6] = [
+        Region::Central,
+        Region::East,
+        Region::Northeast,
+        Region::North,
+        Region::West,
+        Region::South,
+    ];
+
+    /// The first digits of the province codes of the region.
+    pub fn code_prefixes(&self) -> &'static [i32] {
+        match self {
+            Region::Central => &[1],
+            Region::East => &[2],
+            Region::Northeast => &[3, 4],
+            Region::North => &[5, 6],
+            Region::West => &[7],
+            Region::South => &[8, 9],
+        }
+    }
+
+    /// The region of a province, `None` for the national total.
+    pub fn of(province: ProvinceCode) -> Option<Region> {
+        let prefix = province.value() / 10;
+        Region::ALL
+            .into_iter()
+            .find(|region| region.code_prefixes().contains(&prefix))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Region::Central => "central",
+            Region::East => "east",
+            Region::Northeast => "northeast",
+            Region::North => "north",
+            Region::West => "west",
+            Region::South => "south",
+        }
+    }
+
+    /// A SQL expression naming the region of the `cc_code` column, NULL for the national total.
+    pub fn sql_case(column: &str) -> String {
+        let branches = Region::ALL
+            .iter()
+            .map(|region| {
+                format!(
+                    "WHEN {} // 10 IN ({}) THEN '{}'",
+                    column,
+                    region
+                        .code_prefixes()
+                        .iter()
+                        .map(|prefix| prefix.to_string())
+                        .collect::<Vec<String>>()
+                        .join(", "),
+                    region.as_str()
+                )
+            })
+            .collect::<Vec<String>>()
+            .join(" ");
+        format!("CASE {} END", branches)
+    }
+}
+
+impl std::str::FromStr for Region {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        Region::ALL
+            .into_iter()
+            .find(|region| region.as_str() == value.trim().to_lowercase())
+            .ok_or_else(|| format!("Unknown region: {}", value))
+    }
+}
+
+/// One version of an administrative area in `dim_admin_area`.
+///
+/// A new version starts whenever the name or the parent of a code changes, or when the code
+/// comes back after missing from some years. `valid_to` is `None` for the versions still
+/// present in the latest year of the warehouse.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdminAreaVersion {
+    pub level: String,
+    pub code: String,
+    pub version: i64,
+    pub name: String,
+    pub parent_code: Option<String>,
+    pub valid_from: i32,
+    pub valid_to: Option<i32>,
+    pub is_current: bool,
+}
+
+impl AdminAreaVersion {
+    fn from_row(row: &Row) -> Result<Self> {
+        Ok(AdminAreaVersion {
+            level: row.get(0)?,
+            code: row.get(1)?,
+            version: row.get(2)?,
+            name: row.get(3)?,
+            parent_code: row.get(4)?,
+            valid_from: row.get(5)?,
+            valid_to: row.get(6)?,
+            is_current: row.get(7)?,
+        })
+    }
+}
+
+const DIM_ADMIN_AREA_COLUMNS: &str =
+    "level, code, version, name, parent_code, valid_from, valid_to, is_current";
+
+/// Rebuilds `dim_admin_area`, the SCD Type 2 history of the areas found in `thai_population`.
+///
+/// The dimension is derived from every ingested year at once, so rebuilding it after a load
+/// always gives the same history for the same warehouse.
+///
+/// # Arguments
+///
+/// * `conn` - A reference to a DuckDB Connection holding the 'thai_population' table.
+///
+/// # Returns
+///
+/// * `Result<usize>` - The number of versions written.
+///
+pub fn build_dim_admin_area(conn: &Connection) -> Result<usize> {
+    conn.execute(
+        "CREATE OR REPLACE TABLE dim_admin_area AS
+        WITH observed AS (
+            SELECT
+                'province' AS level,
+                CAST(cc_code AS TEXT) AS code,
+                trim(cc_desc) AS name,
+                CAST(NULL AS TEXT) AS parent_code,
+                data_year
+            FROM thai_population
+            WHERE cc_code IS NOT NULL
+            UNION ALL
+            SELECT
+                'district',
+                trim(ccaatt_code),
+                trim(ccaatt_desc),
+                CAST(cc_code AS TEXT),
+                data_year
+            FROM thai_population
+            WHERE NOT regexp_matches(coalesce(trim(ccaatt_code), ''), '^0*$')
+        ),
+        per_year AS (
+            SELECT level, code, min(name) AS name, min(parent_code) AS parent_code, data_year
+            FROM observed
+            GROUP BY level, code, data_year
+        ),
+        changes AS (
+            SELECT
+                *,
+                CASE
+                    WHEN lag(data_year) OVER w IS NULL THEN 1
+                    WHEN lag(data_year) OVER w <> data_year - 1 THEN 1
+                    WHEN lag(name) OVER w IS DISTINCT FROM name THEN 1
+                    WHEN lag(parent_code) OVER w IS DISTINCT FROM parent_code THEN 1
+                    ELSE 0
+                END AS starts_version
+            FROM per_year
+            WINDOW w AS (PARTITION BY level, code ORDER BY data_year)
+        ),
+        versions AS (
+            SELECT
+                *,
+                sum(starts_version) OVER (PARTITION BY level, code ORDER BY data_year) AS version
+            FROM changes
+        ),
+        latest AS (SELECT max(data_year) AS data_year FROM thai_population)
+        SELECT
+            level,
+            code,
+            CAST(version AS BIGINT) AS version,
+            any_value(name) AS name,
+            any_value(parent_code) AS parent_code,
+            min(versions.data_year) AS valid_from,
+            CASE
+                WHEN max(versions.data_year) = any_value(latest.data_year) THEN NULL
+                ELSE max(versions.data_year)
+            END AS valid_to,
+            max(versions.data_year) = any_value(latest.data_year) AS is_current
+        FROM versions, latest
+        GROUP BY level, code, version
+        ORDER BY level, code, version",
+        [],
+    )?;
+    conn.query_row("SELECT count(*) FROM dim_admin_area", [], |row| row.get(0))
+}
+
+/// Resolves a code to the version of the area which was valid in a given year.
+///
+/// # Arguments
+///
+/// * `conn` - A reference to a DuckDB Connection holding 'dim_admin_area'.
+/// * `level` - The level the code belongs to.
+/// * `code` - The area code, e.g. `"38"` for a province.
+/// * `year` - The Gregorian year the code is resolved for.
+///
+/// # Returns
+///
+/// * `Result<Option<AdminAreaVersion>>` - The version valid in `year`, if the code existed then.
+///
+pub fn resolve_admin_area(
+    conn: &Connection,
+    level: AdminLevel,
+    code: &str,
+    year: i32,
+) -> Result<Option<AdminAreaVersion>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM dim_admin_area
+        WHERE level = ? AND code = ? AND valid_from <= ? AND coalesce(valid_to, ?) >= ?",
+        DIM_ADMIN_AREA_COLUMNS
+    ))?;
+    let mut rows = stmt.query(params![level.as_str(), code, year, year, year])?;
+    match rows.next()? {
+        Some(row) => Ok(Some(AdminAreaVersion::from_row(row)?)),
+        None => Ok(None),
+    }
+}
+
+/// Lists every version of an area, oldest first.
+pub fn admin_area_history(
+    conn: &Connection,
+    level: AdminLevel,
+    code: &str,
+) -> Result<Vec<AdminAreaVersion>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM dim_admin_area WHERE level = ? AND code = ? ORDER BY version",
+        DIM_ADMIN_AREA_COLUMNS
+    ))?;
+    let versions = stmt
+        .query_map(params![level.as_str(), code], AdminAreaVersion::from_row)?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(versions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insert(conn: &Connection, year: i32, cc_code: i32, cc_desc: &str, ccaatt: &str, desc: &str) {
+        conn.execute(
+            "INSERT INTO thai_population (data_year, cc_code, cc_desc, ccaatt_code, ccaatt_desc)
+            VALUES (?, ?, ?, ?, ?)",
+            params![year, cc_code, cc_desc, ccaatt, desc],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_region_of_province() {
+        assert_eq!(
+            Region::of(ProvinceCode::new(10).unwrap()),
+            Some(Region::Central)
+        );
+        assert_eq!(
+            Region::of(ProvinceCode::new(38).unwrap()),
+            Some(Region::Northeast)
+        );
+        assert_eq!(
+            Region::of(ProvinceCode::new(95).unwrap()),
+            Some(Region::South)
+        );
+        assert_eq!(Region::of(ProvinceCode::new(0).unwrap()), None);
+        assert_eq!(" North".parse::<Region>(), Ok(Region::North));
+    }
+
+    #[test]
+    fn test_dim_admin_area_tracks_renames_and_new_codes() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(
+            "CREATE TABLE thai_population (
+                data_year INTEGER, cc_code INTEGER, cc_desc TEXT, ccaatt_code TEXT, ccaatt_desc TEXT
+            )",
+            [],
+        )
+        .unwrap();
+        insert(&conn, 2010, 43, "Nong Khai", "430100", "Mueang Nong Khai");
+        insert(&conn, 2011, 43, "Nong Khai", "430100", "Mueang Nong Khai");
+        // Bueng Kan is split off Nong Khai and the district moves to the new province
+        insert(&conn, 2012, 43, "Nong Khai", "0", "");
+        insert(&conn, 2012, 38, "Bueng Kan", "380100", "Mueang Bueng Kan");
+        insert(&conn, 2013, 43, "Nongkhai", "", "");
+        insert(&conn, 2013, 38, "Bueng Kan", "380100", "Mueang Bueng Kan");
+
+        assert_eq!(build_dim_admin_area(&conn).unwrap(), 5);
+
+        let history = admin_area_history(&conn, AdminLevel::Province, "43").unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(
+            (history[0].valid_from, history[0].valid_to),
+            (2010, Some(2012))
+        );
+        assert_eq!(history[1].name, "Nongkhai");
+        assert!(history[1].is_current);
+
+        let resolved = resolve_admin_area(&conn, AdminLevel::Province, "43", 2011)
+            .unwrap()
+            .unwrap();
+        assert_eq!(resolved.name, "Nong Khai");
+        assert!(resolve_admin_area(&conn, AdminLevel::Province, "38", 2011)
+            .unwrap()
+            .is_none());
+
+        let district = resolve_admin_area(&conn, AdminLevel::District, "380100", 2013)
+            .unwrap()
+            .unwrap();
+        assert_eq!(district.parent_code, Some("38".to_string()));
+        assert_eq!(district.valid_to, None);
+    }
+}
@@ -0,0 +1,360 @@
+use crate::codes::{DistrictCode, ProvinceCode, SubdistrictCode};
+use crate::query::provinces;
+use async_graphql::{
+    Context, EmptyMutation, EmptySubscription, Enum, Object, Schema, SimpleObject,
+};
+use duckdb::{params, Connection, OptionalExt};
+use std::sync::Mutex;
+
+/// The GraphQL schema of the warehouse, served on `POST /graphql` by `serve`.
+pub type PopulationSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+/// Builds the schema over its own connection to the warehouse.
+///
+/// ```graphql
+/// {
+///   area(code: "38") {
+///     name
+///     population(year: 2023) { total }
+///     children { code name population(year: 2023) { total } }
+///   }
+/// }
+/// ```
+pub fn build_schema(conn: Connection) -> PopulationSchema {
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .data(Mutex::new(conn))
+        .finish()
+}
+
+/// Level of an area in the province, district, sub-district hierarchy of the DOPA codes.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AreaLevel {
+    Province,
+    District,
+    Subdistrict,
+}
+
+impl AreaLevel {
+    /// A SQL expression of the zero-padded code of the level.
+    fn code_expr(&self) -> &'static str {
+        match self {
+            AreaLevel::Province => "lpad(CAST(cc_code AS TEXT), 2, '0')",
+            AreaLevel::District => "ccaatt_code",
+            AreaLevel::Subdistrict => "ccaattmm_code",
+        }
+    }
+
+    fn name_column(&self) -> &'static str {
+        match self {
+            AreaLevel::Province => "cc_desc",
+            AreaLevel::District => "ccaatt_desc",
+            AreaLevel::Subdistrict => "ccaattmm_desc",
+        }
+    }
+
+    fn child(&self) -> Option<AreaLevel> {
+        match self {
+            AreaLevel::Province => Some(AreaLevel::District),
+            AreaLevel::District => Some(AreaLevel::Subdistrict),
+            AreaLevel::Subdistrict => None,
+        }
+    }
+
+    /// A SQL condition keeping the lines of the level itself rather than of its children,
+    /// whose codes are zeros below the level.
+    fn own_lines(&self) -> &'static str {
+        match self {
+            AreaLevel::Province => "regexp_matches(ccaatt_code, '^0*$')",
+            AreaLevel::District => "regexp_matches(ccaattmm_code, '^0*$')",
+            AreaLevel::Subdistrict => "true",
+        }
+    }
+}
+
+/// Counts of an area for a year.
+#[derive(SimpleObject, Debug, Clone, PartialEq)]
+pub struct Population {
+    pub year: i32,
+    pub male: i64,
+    pub female: i64,
+    pub total: i64,
+    pub house: i64,
+}
+
+/// A province, district or sub-district, named after its latest name in the warehouse.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdminArea {
+    pub level: AreaLevel,
+    pub code: String,
+    pub name: String,
+}
+
+impl AdminArea {
+    /// Looks up an area of the warehouse, `None` when no line has its code.
+    fn find(conn: &Connection, level: AreaLevel, code: String) -> duckdb::Result<Option<Self>> {
+        conn.query_row(
+            &format!(
+                "SELECT arg_max({}, data_year) FROM thai_population WHERE {} = ? \
+                HAVING count(*) > 0",
+                level.name_column(),
+                level.code_expr()
+            ),
+            params![code],
+            |row| row.get::<_, String>(0),
+        )
+        .optional()
+        .map(|name| name.map(|name| AdminArea { level, code, name }))
+    }
+}
+
+fn lock<'c>(ctx: &Context<'c>) -> std::sync::MutexGuard<'c, Connection> {
+    ctx.data_unchecked::<Mutex<Connection>>().lock().unwrap()
+}
+
+#[Object]
+impl AdminArea {
+    async fn level(&self) -> AreaLevel {
+        self.level
+    }
+
+    /// The code zero-padded to the width of its level.
+    async fn code(&self) -> &str {
+        &self.code
+    }
+
+    async fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The province of a district or the district of a sub-district.
+    async fn parent(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<AdminArea>> {
+        let parent = match self.level {
+            AreaLevel::Province => return Ok(None),
+            AreaLevel::District => (
+                AreaLevel::Province,
+                self.code.parse::<DistrictCode>()?.province().to_string(),
+            ),
+            AreaLevel::Subdistrict => (
+                AreaLevel::District,
+                self.code.parse::<SubdistrictCode>()?.district().to_string(),
+            ),
+        };
+        Ok(AdminArea::find(&lock(ctx), parent.0, parent.1)?)
+    }
+
+    /// The areas one level down, ordered by code.
+    async fn children(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<AdminArea>> {
+        let Some(child) = self.level.child() else {
+            return Ok(vec![]);
+        };
+        let conn = lock(ctx);
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {child_code}, arg_max({child_name}, data_year)
+            FROM thai_population
+            WHERE {code} = ? AND NOT regexp_matches({child_code}, '^0*$')
+            GROUP BY 1
+            ORDER BY 1",
+            child_code = child.code_expr(),
+            child_name = child.name_column(),
+            code = self.level.code_expr()
+        ))?;
+        let children = stmt
+            .query_map(params![self.code], |row| {
+                Ok(AdminArea {
+                    level: child,
+                    code: row.get(0)?,
+                    name: row.get(1)?,
+                })
+            })?
+            .collect::<duckdb::Result<Vec<AdminArea>>>()?;
+        Ok(children)
+    }
+
+    /// The counts of the area's own lines for a Gregorian year, `null` when it has none.
+    async fn population(
+        &self,
+        ctx: &Context<'_>,
+        year: i32,
+    ) -> async_graphql::Result<Option<Population>> {
+        let population = lock(ctx)
+            .query_row(
+                &format!(
+                    "SELECT
+                        CAST(sum(male) AS BIGINT),
+                        CAST(sum(female) AS BIGINT),
+                        CAST(sum(total) AS BIGINT),
+                        CAST(sum(house) AS BIGINT)
+                    FROM thai_population
+                    WHERE data_year = ? AND {} = ? AND {}
+                    HAVING count(*) > 0",
+                    self.level.code_expr(),
+                    self.level.own_lines()
+                ),
+                params![year, self.code],
+                |row| {
+                    Ok(Population {
+                        year,
+                        male: row.get(0)?,
+                        female: row.get(1)?,
+                        total: row.get(2)?,
+                        house: row.get(3)?,
+                    })
+                },
+            )
+            .optional()?;
+        Ok(population)
+    }
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// The provinces of the warehouse, without the national total.
+    async fn provinces(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<AdminArea>> {
+        Ok(provinces(&lock(ctx))?
+            .into_iter()
+            .map(|province| AdminArea {
+                level: AreaLevel::Province,
+                code: province.cc_code.to_string(),
+                name: province.cc_desc,
+            })
+            .collect())
+    }
+
+    /// An area from its code, its level being told by the number of digits: up to 2 for a
+    /// province, 6 for a district and 8 for a sub-district.
+    async fn area(
+        &self,
+        ctx: &Context<'_>,
+        code: String,
+    ) -> async_graphql::Result<Option<AdminArea>> {
+        let (level, code) = match code.trim().len() {
+            0..=2 => (
+                AreaLevel::Province,
+                code.parse::<ProvinceCode>()?.to_string(),
+            ),
+            3..=6 => (
+                AreaLevel::District,
+                code.parse::<DistrictCode>()?.to_string(),
+            ),
+            7..=8 => (
+                AreaLevel::Subdistrict,
+                code.parse::<SubdistrictCode>()?.to_string(),
+            ),
+            _ => return Err(format!("Invalid area code {:?}", code).into()),
+        };
+        Ok(AdminArea::find(&lock(ctx), level, code)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsers::population::PopulationRow;
+    use crate::schema::RowSchema;
+    use futures::executor::block_on;
+    use serde_json::{json, Value};
+
+    fn schema() -> PopulationSchema {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(
+            &format!(
+                "CREATE TABLE thai_population (data_year INTEGER, {})",
+                PopulationRow::column_definitions()
+            ),
+            [],
+        )
+        .unwrap();
+        for (district, subdistrict, name, total) in [
+            ("0", "0", "Bueng Kan", 400),
+            ("380100", "0", "Mueang", 300),
+            ("380100", "38010001", "Bueng Kan", 120),
+            ("380100", "38010002", "Wisit", 180),
+            ("380200", "0", "Chaiyaphon", 100),
+        ] {
+            conn.execute(
+                &format!(
+                    "INSERT INTO thai_population (data_year, {}) VALUES (2023, {})",
+                    PopulationRow::column_list(),
+                    PopulationRow::placeholders()
+                ),
+                params![
+                    "6612",
+                    38,
+                    "Bueng Kan",
+                    "3801",
+                    "",
+                    district,
+                    name,
+                    subdistrict,
+                    name,
+                    total / 2,
+                    total / 2,
+                    total,
+                    1
+                ],
+            )
+            .unwrap();
+        }
+        build_schema(conn)
+    }
+
+    fn execute(schema: &PopulationSchema, query: &str) -> Value {
+        let response = block_on(schema.execute(query));
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        response.data.into_json().unwrap()
+    }
+
+    #[test]
+    fn test_drill_down_from_a_province() {
+        let schema = schema();
+        let data = execute(
+            &schema,
+            r#"{ area(code: "38") {
+                level name population(year: 2023) { total }
+                children { code population(year: 2023) { total } children { name } }
+            } }"#,
+        );
+        assert_eq!(
+            data,
+            json!({ "area": {
+                "level": "PROVINCE",
+                "name": "Bueng Kan",
+                "population": { "total": 400 },
+                "children": [
+                    {
+                        "code": "380100",
+                        "population": { "total": 300 },
+                        "children": [{ "name": "Bueng Kan" }, { "name": "Wisit" }]
+                    },
+                    { "code": "380200", "population": { "total": 100 }, "children": [] }
+                ]
+            } })
+        );
+    }
+
+    #[test]
+    fn test_parent_and_missing_areas() {
+        let schema = schema();
+        let data = execute(
+            &schema,
+            r#"{
+                area(code: "38010002") { level parent { code parent { code } } }
+                missing: area(code: "10") { name }
+                provinces { code population(year: 1999) { total } }
+            }"#,
+        );
+        assert_eq!(data["area"]["level"], "SUBDISTRICT");
+        assert_eq!(data["area"]["parent"]["code"], "380100");
+        assert_eq!(data["area"]["parent"]["parent"]["code"], "38");
+        assert_eq!(data["missing"], Value::Null);
+        assert_eq!(
+            data["provinces"],
+            json!([{ "code": "38", "population": null }])
+        );
+        assert!(!block_on(schema.execute(r#"{ area(code: "x") { name } }"#))
+            .errors
+            .is_empty());
+    }
+}
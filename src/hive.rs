@@ -0,0 +1,165 @@
+use crate::parsers::population::PopulationRow;
+use crate::query::PopulationRecord;
+use crate::schema::RowSchema;
+use duckdb::arrow::record_batch::RecordBatch;
+use duckdb::{Connection, Result};
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+/// Reads back a Hive partitioned export of `thai_population`.
+///
+/// Only the `data_year=` directories of the selected years are handed to `read_parquet`, so
+/// the other partitions are never opened.
+pub struct HiveReader {
+    conn: Connection,
+    root: PathBuf,
+    years: Option<RangeInclusive<i32>>,
+}
+
+impl HiveReader {
+    /// Opens the export rooted at `root`, e.g. `./datasets/thai_population`.
+    pub fn open(root: impl AsRef<Path>) -> Result<Self> {
+        Ok(HiveReader {
+            conn: Connection::open_in_memory()?,
+            root: root.as_ref().to_path_buf(),
+            years: None,
+        })
+    }
+
+    /// Only reads the partitions of `years`, both ends included.
+    pub fn years(mut self, years: RangeInclusive<i32>) -> Self {
+        self.years = Some(years);
+        self
+    }
+
+    /// Lists the years found as `data_year=` partitions, oldest first.
+    pub fn partitions(&self) -> std::io::Result<Vec<i32>> {
+        if !self.root.exists() {
+            return Ok(vec![]);
+        }
+        let mut years = fs::read_dir(&self.root)?
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                entry
+                    .file_name()
+                    .to_str()?
+                    .strip_prefix("data_year=")?
+                    .parse::<i32>()
+                    .ok()
+            })
+            .collect::<Vec<i32>>();
+        years.sort_unstable();
+        Ok(years)
+    }
+
+    /// The `read_parquet` call over the selected partitions, `None` when there is none.
+    fn source(&self) -> Result<Option<String>> {
+        let partitions = self
+            .partitions()
+            .map_err(|e| duckdb::Error::ToSqlConversionFailure(Box::new(e)))?;
+        let files = partitions
+            .into_iter()
+            .filter(|year| self.years.as_ref().is_none_or(|years| years.contains(year)))
+            .map(|year| {
+                let glob = self
+                    .root
+                    .join(format!("data_year={}", year))
+                    .join("*.parquet*");
+                format!("'{}'", glob.display().to_string().replace('\'', "''"))
+            })
+            .collect::<Vec<String>>();
+        if files.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(format!(
+            "read_parquet([{}], hive_partitioning = true)",
+            files.join(", ")
+        )))
+    }
+
+    fn select(&self, source: &str) -> String {
+        format!(
+            "SELECT CAST(data_year AS INTEGER) AS data_year, {} FROM {} ORDER BY data_year, cc_code",
+            PopulationRow::column_list(),
+            source
+        )
+    }
+
+    /// Reads the selected partitions into rows, ordered by year and province.
+    pub fn rows(&self) -> Result<Vec<PopulationRecord>> {
+        let Some(source) = self.source()? else {
+            return Ok(vec![]);
+        };
+        let mut stmt = self.conn.prepare(&self.select(&source))?;
+        let records = stmt.query_map([], PopulationRecord::from_row)?.collect();
+        records
+    }
+
+    /// Reads the selected partitions as Arrow record batches.
+    pub fn record_batches(&self) -> Result<Vec<RecordBatch>> {
+        let Some(source) = self.source()? else {
+            return Ok(vec![]);
+        };
+        let mut stmt = self.conn.prepare(&self.select(&source))?;
+        let batches = stmt.query_arrow([])?.collect();
+        Ok(batches)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use duckdb::params;
+    use std::env;
+
+    #[test]
+    fn test_reader_prunes_partitions() {
+        let root = env::temp_dir().join(format!("rust_hive_export_{}", std::process::id()));
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(
+            &format!(
+                "CREATE TABLE thai_population (data_year INTEGER, {})",
+                PopulationRow::column_definitions()
+            ),
+            [],
+        )
+        .unwrap();
+        for year in [2021, 2022, 2023] {
+            conn.execute(
+                &format!(
+                    "INSERT INTO thai_population (data_year, {}) VALUES (?, {})",
+                    PopulationRow::column_list(),
+                    PopulationRow::placeholders()
+                ),
+                params![year, "6612", 10, "Bangkok", "1099", "", "100100", "", "0", "", 1, 2, 3, 4],
+            )
+            .unwrap();
+        }
+        conn.execute(
+            &format!(
+                "COPY thai_population TO '{}' (FORMAT PARQUET, PARTITION_BY (data_year), \
+                COMPRESSION GZIP, FILE_EXTENSION 'parquet.gz')",
+                root.display()
+            ),
+            [],
+        )
+        .unwrap();
+
+        let reader = HiveReader::open(&root).unwrap();
+        assert_eq!(reader.partitions().unwrap(), vec![2021, 2022, 2023]);
+
+        let reader = reader.years(2022..=2030);
+        let rows = reader.rows().unwrap();
+        assert_eq!(
+            rows.iter().map(|r| r.data_year).collect::<Vec<i32>>(),
+            vec![2022, 2023]
+        );
+        assert_eq!(rows[0].row.ccaatt_code.as_str(), "100100");
+        let batches = reader.record_batches().unwrap();
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 2);
+
+        assert!(reader.years(1990..=1991).rows().unwrap().is_empty());
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,142 @@
+use duckdb::{Connection, Result};
+
+/// Rebuilds `thai_population_indicators` from `thai_population`, one row per year and
+/// `cc_code`, the national total included.
+///
+/// * `sex_ratio` - males per 100 females.
+/// * `persons_per_household` - `total / house`.
+/// * `yoy_growth` - relative change of `total` since the previous year, NULL when the area is
+///   missing from it.
+/// * `national_share` - share of the national total of the year, read from the `cc_code` 0
+///   line, or summed over the provinces when the file has none.
+///
+/// Ratios are NULL instead of infinite when their denominator is zero.
+///
+/// # Arguments
+///
+/// * `conn` - A reference to a DuckDB Connection to the warehouse.
+///
+/// # Returns
+///
+/// * `Result<usize>` - The number of rows of the table.
+///
+pub fn build_indicators(conn: &Connection) -> Result<usize> {
+    conn.execute(
+        "CREATE OR REPLACE TABLE thai_population_indicators AS
+        WITH national AS (
+            SELECT
+                data_year,
+                coalesce(
+                    max(total) FILTER (WHERE cc_code = 0),
+                    sum(total) FILTER (WHERE cc_code <> 0)
+                ) AS total
+            FROM thai_population
+            GROUP BY data_year
+        )
+        SELECT
+            p.data_year,
+            p.cc_code,
+            p.cc_desc,
+            p.male / nullif(p.female, 0) * 100 AS sex_ratio,
+            p.total / nullif(p.house, 0) AS persons_per_household,
+            (p.total - previous.total) / nullif(previous.total, 0) AS yoy_growth,
+            p.total / nullif(national.total, 0) AS national_share
+        FROM thai_population p
+        JOIN national USING (data_year)
+        LEFT JOIN thai_population previous
+            ON previous.cc_code = p.cc_code AND previous.data_year = p.data_year - 1
+        ORDER BY p.data_year, p.cc_code",
+        [],
+    )?;
+    conn.query_row(
+        "SELECT count(*) FROM thai_population_indicators",
+        [],
+        |row| row.get(0),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsers::population::PopulationRow;
+    use crate::schema::RowSchema;
+    use duckdb::params;
+
+    /// `data_year`, `cc_code` and the four indicators.
+    type Indicators = (i32, i32, f64, Option<f64>, Option<f64>, f64);
+
+    #[test]
+    fn test_indicators_are_derived_per_year_and_province() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(
+            &format!(
+                "CREATE TABLE thai_population (data_year INTEGER, {})",
+                PopulationRow::column_definitions()
+            ),
+            [],
+        )
+        .unwrap();
+        for (year, cc_code, male, female, house) in [
+            (2022, 0, 150, 150, 100),
+            (2022, 10, 45, 55, 40),
+            (2023, 10, 48, 60, 0),
+            (2023, 38, 90, 90, 60),
+        ] {
+            conn.execute(
+                &format!(
+                    "INSERT INTO thai_population (data_year, {}) VALUES (?, {})",
+                    PopulationRow::column_list(),
+                    PopulationRow::placeholders()
+                ),
+                params![
+                    year,
+                    "6612",
+                    cc_code,
+                    "p",
+                    "0",
+                    "",
+                    "0",
+                    "",
+                    "0",
+                    "",
+                    male,
+                    female,
+                    male + female,
+                    house
+                ],
+            )
+            .unwrap();
+        }
+        assert_eq!(build_indicators(&conn).unwrap(), 4);
+
+        let indicators: Vec<Indicators> = conn
+            .prepare(
+                "SELECT data_year, cc_code, sex_ratio, persons_per_household, yoy_growth,
+                    national_share
+                FROM thai_population_indicators",
+            )
+            .unwrap()
+            .query_map([], |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                    row.get(5)?,
+                ))
+            })
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        // 2022 has a national line, 2023 is shared out over its provinces
+        assert_eq!(indicators[0], (2022, 0, 100.0, Some(3.0), None, 1.0));
+        assert_eq!(indicators[1].5, 100.0 / 300.0);
+        assert_eq!(
+            indicators[2],
+            (2023, 10, 80.0, None, Some(0.08), 108.0 / 288.0)
+        );
+        assert_eq!(indicators[3].2, 100.0);
+        assert_eq!(indicators[3].4, None);
+    }
+}
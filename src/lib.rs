@@ -1 +1,24 @@
+pub mod anomalies;
+pub mod calendar;
+pub mod checkpoint;
+pub mod codes;
+pub mod crosswalk;
+pub mod geography;
+pub mod graphql;
+pub mod hive;
+pub mod indicators;
+pub mod logging;
+pub mod metrics;
+pub mod migrations;
 pub mod parsers;
+pub mod profile;
+pub mod progress;
+pub mod projection;
+#[cfg(feature = "python")]
+mod python;
+pub mod query;
+pub mod reconciliation;
+pub mod repl;
+pub mod schema;
+pub mod serve;
+pub mod table_functions;
@@ -0,0 +1,123 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::EnvFilter;
+
+/// Environment variable selecting the log format, either `pretty` (default) or `json`.
+pub const LOG_FORMAT_ENV: &str = "RUST_HIVE_LOG_FORMAT";
+/// Environment variable holding a file path to write logs into instead of stderr.
+pub const LOG_FILE_ENV: &str = "RUST_HIVE_LOG_FILE";
+/// Environment variable holding the `tracing` filter directives, e.g. `rust_hive=debug`.
+pub const LOG_FILTER_ENV: &str = "RUST_LOG";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum LogFormat {
+    #[default]
+    Pretty,
+    Json,
+}
+
+impl LogFormat {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_lowercase().as_str() {
+            "pretty" => Ok(LogFormat::Pretty),
+            "json" => Ok(LogFormat::Json),
+            other => Err(format!("Unknown log format: {}", other)),
+        }
+    }
+}
+
+/// How the `tracing` subscriber of a binary is set up.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogConfig {
+    pub format: LogFormat,
+    pub file: Option<PathBuf>,
+    pub filter: String,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            format: LogFormat::Pretty,
+            file: None,
+            filter: "info".to_string(),
+        }
+    }
+}
+
+impl LogConfig {
+    /// Reads the configuration from `RUST_HIVE_LOG_FORMAT`, `RUST_HIVE_LOG_FILE` and `RUST_LOG`.
+    ///
+    /// Unset variables fall back to pretty printed `info` logs on stderr.
+    pub fn from_env() -> Result<Self, String> {
+        let mut config = LogConfig::default();
+        if let Ok(format) = env::var(LOG_FORMAT_ENV) {
+            config.format = LogFormat::parse(&format)?;
+        }
+        if let Ok(file) = env::var(LOG_FILE_ENV) {
+            config.file = Some(PathBuf::from(file));
+        }
+        if let Ok(filter) = env::var(LOG_FILTER_ENV) {
+            config.filter = filter;
+        }
+        Ok(config)
+    }
+}
+
+/// Installs the global `tracing` subscriber described by `config`.
+///
+/// Logs written to a file go through a background writer; the returned guard must be kept
+/// alive until the end of `main` so buffered lines are flushed on exit.
+///
+/// # Returns
+///
+/// A `Result` which is:
+/// * `Ok` with the guard of the file writer, or `None` when logging to stderr.
+/// * `Err` if the filter is invalid, the log file cannot be opened or a subscriber is already set.
+pub fn init_tracing(config: &LogConfig) -> io::Result<Option<WorkerGuard>> {
+    let filter = EnvFilter::try_new(&config.filter)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+    let (writer, guard) = match &config.file {
+        Some(path) => {
+            if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+                fs::create_dir_all(parent)?;
+            }
+            let file = fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?;
+            let (writer, guard) = tracing_appender::non_blocking(file);
+            (BoxMakeWriter::new(writer), Some(guard))
+        }
+        None => (BoxMakeWriter::new(io::stderr), None),
+    };
+
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(writer)
+        .with_ansi(config.file.is_none())
+        .with_thread_names(true);
+    let installed = match config.format {
+        LogFormat::Pretty => builder.pretty().try_init(),
+        LogFormat::Json => builder.json().with_current_span(true).try_init(),
+    };
+    installed.map_err(|e| io::Error::new(io::ErrorKind::AlreadyExists, e))?;
+
+    Ok(guard)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_format_parse() {
+        assert_eq!(LogFormat::parse("pretty"), Ok(LogFormat::Pretty));
+        assert_eq!(LogFormat::parse(" JSON "), Ok(LogFormat::Json));
+        assert!(LogFormat::parse("xml").is_err());
+    }
+}
@@ -1,15 +1,37 @@
 mod databases;
-mod parsers;
+use clap::{Parser, Subcommand};
 use databases::duckdb_functions::{
-    create_duck_db_table, generate_insert_sql_given_row_struct, write_into_hive_partition,
+    delete_year, drop_year_snapshot, insert_population_row, open_warehouse, record_source,
+    record_year_changes, snapshot_year, stored_content_hash, write_indicators_into_hive_partition,
+    write_into_hive_partition, write_years_into_hive_partition, WAREHOUSE_MIGRATIONS,
 };
 use duckdb::{Connection, Error as DuckDBError, Result};
 
 use reqwest::Error as RequestwestError;
+use rust_hive::anomalies::detect_anomalies;
+use rust_hive::calendar::{to_short_buddhist_year, validate_data_year, FIRST_DATA_YEAR};
+use rust_hive::checkpoint::{content_hash, Checkpoint, Stage, CHECKPOINT_PATH};
+use rust_hive::crosswalk::{build_harmonised_view, Crosswalk, CROSSWALK_PATH};
+use rust_hive::geography::build_dim_admin_area;
+use rust_hive::indicators::build_indicators;
+use rust_hive::logging::{init_tracing, LogConfig};
+use rust_hive::metrics::{metrics, MetricsConfig, MetricsListener};
+use rust_hive::migrations::{migrate, migration_status};
 use rust_hive::parsers::population::PopulationRow;
+use rust_hive::profile::{ProfileReport, PROFILE_REPORT_PATH};
+use rust_hive::progress::{
+    IngestionEvent, ProgressReporter, TerminalProgress, YearStatus, SUMMARY_PATH,
+};
+use rust_hive::projection::{backtest, build_projections, Model};
+use rust_hive::reconciliation::{reconcile, separate_aggregates};
+use rust_hive::repl::{attach_hive_export, Repl, ReplError, HISTORY_PATH};
+use rust_hive::serve::{ApiServer, DEFAULT_SERVE_ADDR};
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 use thiserror::Error;
+use tracing::{debug_span, error, info, info_span, warn};
 
 // Custom error handling
 #[derive(Error, Debug)]
@@ -20,24 +42,122 @@ enum IngestionError {
     Io(#[from] std::io::Error),
     #[error("Requestwest error: {0}")]
     Requestwest(#[from] RequestwestError),
+    #[error("Fetch error: {0}")]
+    Fetch(String),
     #[error("Parse error: {0}")]
     Parse(String),
+    #[error("Configuration error: {0}")]
+    Config(String),
+    #[error("REPL error: {0}")]
+    Repl(#[from] ReplError),
 }
 
-/// Converts a Gregorian year to a Thai year.
-///
-/// This function takes an integer representing a Gregorian year and returns the corresponding Thai year.
-/// The conversion is based on the Thai calendar system, which starts from the year 2500.
-///
-/// # Parameters
-///
-/// * `year` - An integer representing the Gregorian year.
-///
-/// # Returns
-///
-/// An integer representing the corresponding Thai year in short form.
-fn convert_to_thai_year(year: i32) -> i32 {
-    year + 543 - 2500
+/// Ingests the DOPA population statistics into DuckDB and Hive partitions.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Cli {
+    /// Only load the years whose source file changed since they were loaded into the
+    /// warehouse, and only rewrite their `data_year=` partitions.
+    #[arg(long)]
+    incremental: bool,
+    /// Year whose province geography `thai_population_harmonised` maps every year onto.
+    /// Defaults to the last ingested year.
+    #[arg(long, value_name = "YEAR")]
+    reference_year: Option<i32>,
+    /// JSON file with the split and merge mappings between province codes.
+    #[arg(long, value_name = "PATH", default_value = CROSSWALK_PATH)]
+    crosswalk: PathBuf,
+    /// File the profiling report of the run is written to, as HTML when it ends with `.html`
+    /// and as Markdown otherwise.
+    #[arg(long, value_name = "PATH", default_value = PROFILE_REPORT_PATH)]
+    profile_report: PathBuf,
+    /// Move the aggregate lines, whose counts are also on their child lines, out of
+    /// `thai_population` into `thai_population_aggregates` after reconciling them.
+    #[arg(long)]
+    separate_aggregates: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+/// Commands run instead of the ingestion.
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Lists the migrations of the warehouse schema and whether they are applied, without
+    /// applying them.
+    Migrations,
+    /// Serves a read-only REST API over the warehouse: `/provinces`,
+    /// `/population?year=&cc_code=` and `/timeseries/{cc_code}`, as JSON or CSV, and a GraphQL
+    /// endpoint on `/graphql`.
+    Serve {
+        /// Address to listen on.
+        #[arg(long, default_value = DEFAULT_SERVE_ADDR)]
+        addr: String,
+        /// Seconds a query result is kept in the cache.
+        #[arg(long, value_name = "SECONDS", default_value_t = 60)]
+        cache_ttl: u64,
+    },
+    /// Opens an interactive SQL session over the warehouse, with the `latest_year`,
+    /// `region_totals` and `sex_ratio` views and `.export` to CSV or Parquet.
+    Repl {
+        /// Reads the Hive export rooted at this directory instead of the warehouse.
+        #[arg(long, value_name = "PATH")]
+        hive: Option<PathBuf>,
+    },
+    /// Projects the `total` of every `cc_code` with several trend models into
+    /// `thai_population_projections`, and backtests them on the last years.
+    Project {
+        /// Number of years projected after the last year of the warehouse.
+        #[arg(long, value_name = "YEARS", default_value_t = 10)]
+        horizon: usize,
+        /// Number of last years held out of the fit to measure the models' errors.
+        #[arg(long, value_name = "YEARS", default_value_t = 3)]
+        holdout: usize,
+    },
+}
+
+/// Prints every migration of the warehouse with its status.
+fn show_migrations(conn: &Connection) -> Result<(), IngestionError> {
+    let status = migration_status(conn, &WAREHOUSE_MIGRATIONS)?;
+    let pending = status.iter().filter(|migration| !migration.applied).count();
+    for migration in status {
+        println!(
+            "{:>4}  {:<8}  {}",
+            migration.version,
+            if migration.applied {
+                "applied"
+            } else {
+                "pending"
+            },
+            migration.description
+        );
+    }
+    println!("{} pending migration(s)", pending);
+    Ok(())
+}
+
+/// Writes the projections and prints the mean backtest error of every model.
+fn project(conn: &Connection, horizon: usize, holdout: usize) -> Result<(), IngestionError> {
+    let rows = build_projections(conn, horizon)?;
+    println!("Projected {} rows into thai_population_projections", rows);
+    let backtests = backtest(conn, holdout)?;
+    for model in Model::ALL {
+        let errors = backtests
+            .iter()
+            .filter(|backtest| backtest.model == model)
+            .map(|backtest| backtest.mape)
+            .collect::<Vec<f64>>();
+        if errors.is_empty() {
+            continue;
+        }
+        println!(
+            "{:<22}  MAPE {:>6.2}% over {} area(s), {} year(s) held out",
+            model.as_str(),
+            errors.iter().sum::<f64>() / errors.len() as f64 * 100.0,
+            errors.len(),
+            holdout
+        );
+    }
+    Ok(())
 }
 
 /// Retrieves statistical data for a given year from a specific URL.
@@ -56,21 +176,31 @@ fn convert_to_thai_year(year: i32) -> i32 {
 /// * `Ok` containing a `String` of the retrieved data, trimmed of leading/trailing whitespace and newlines.
 /// * `Err` containing a `String` describing the error if the HTTP request fails or returns a non-2xx status code.
 fn get_data_stat_by_year(year: i32) -> Result<String, String> {
-    let thai_year = convert_to_thai_year(year);
+    let thai_year = to_short_buddhist_year(year);
     let url = format!(
         "https://stat.bora.dopa.go.th/new_stat/file/{}/stat_c{}.txt",
         thai_year, thai_year
     );
+    let _span = info_span!("fetch", data_year = year, thai_year, url = %url).entered();
 
     let mut result = String::new();
-    if let Ok(response) = reqwest::blocking::get(url) {
-        if response.status().as_u16() / 100 != 2 {
-            return Err(format!(
-                "Fail request with HTTP code: {:?}",
-                response.status().as_u16()
-            ));
+    let started = Instant::now();
+    match reqwest::blocking::get(url) {
+        Ok(response) => {
+            metrics().observe_http(Some(response.status().as_u16()), started.elapsed());
+            info!(status = response.status().as_u16(), "Received response");
+            if response.status().as_u16() / 100 != 2 {
+                return Err(format!(
+                    "Fail request with HTTP code: {:?}",
+                    response.status().as_u16()
+                ));
+            }
+            result = response.text().ok().unwrap();
+        }
+        Err(e) => {
+            metrics().observe_http(None, started.elapsed());
+            warn!(error = %e, "Request failed");
         }
-        result = response.text().ok().unwrap();
     }
 
     Ok(result.trim_matches(|c| c == ' ' || c == '\n').to_string())
@@ -90,42 +220,181 @@ fn get_data_stat_by_year(year: i32) -> Result<String, String> {
 /// A vector of strings, where each string is a field extracted from the input row.
 fn extract_row(row: &str) -> Vec<String> {
     row.split('|')
-        .into_iter()
         .map(|value| value.to_string())
         .collect::<Vec<String>>()
 }
 
-/// Updates a row in the database with population data.
+/// Parses a line of population data into a `PopulationRow` struct.
 ///
-/// This function processes a line of population data, parses it into a `PopulationRow` struct,
-/// generates an SQL insert statement, and executes it against the provided database connection.
+/// # Parameters
+///
+/// * `line` - A string slice containing the raw population data to be processed.
+///
+/// # Returns
+///
+/// A `Result` which is:
+/// * `Ok` with the parsed `PopulationRow`.
+/// * `Err(IngestionError::Parse)` if the line does not hold a valid row.
+fn parse_line(line: &str) -> Result<PopulationRow, IngestionError> {
+    // Extract fields from the line and convert them into a PopulationRow struct
+    let extracted = extract_row(line.trim_matches(|c| ['|', ' ', '\n', '\r'].contains(&c)));
+    debug_span!("parse")
+        .in_scope(|| PopulationRow::parse(extracted))
+        .map_err(IngestionError::Parse)
+}
+
+/// Inserts a parsed row in the database.
+///
+/// This function generates an SQL insert statement and executes it against the provided
+/// database connection.
 /// Note: Duckdb has internal mechanism which supports ACID
 ///
 /// # Parameters
 ///
 /// * `conn` - A reference to a DuckDB `Connection` object for database operations.
-/// * `line` - A string slice containing the raw population data to be processed.
+/// * `population_row` - The row to insert.
 /// * `year` - An integer representing the year of the population data.
 ///
 /// # Returns
 ///
 /// A `Result` which is:
-/// * `Ok` with a `String` "Updated population" if the operation was successful.
-/// * `Err` with a boxed dynamic `Error` if any step in the process fails.
-fn update_row(conn: &Connection, line: &str, year: i32) -> Result<String, IngestionError> {
-    // Extract fields from the line and convert them into a PopulationRow struct
-    let extracted = extract_row(line.trim_matches(|c| ['|', ' ', '\n', '\r'].contains(&c)));
-    let population_row = match PopulationRow::parse(extracted) {
-        Ok(row) => row,
-        Err(e) => return Err(IngestionError::Parse(e)),
+/// * `Ok(())` if the row was inserted.
+/// * `Err(IngestionError::DuckDB)` if the insert fails.
+fn insert_row(
+    conn: &Connection,
+    population_row: &PopulationRow,
+    year: i32,
+) -> Result<(), IngestionError> {
+    let started = Instant::now();
+    debug_span!("insert").in_scope(|| insert_population_row(conn, year, population_row))?;
+    metrics().observe_insert(started.elapsed());
+    Ok(())
+}
+
+/// What happened to a year once `load_year` is done with it.
+enum YearOutcome {
+    Loaded(u64),
+    Unchanged,
+}
+
+/// Fetches, parses and loads a single year, recording each stage in the checkpoint.
+///
+/// A year whose content hash matches a year already loaded by a previous run is skipped. In
+/// incremental mode the hash is compared with the one stored in the warehouse, otherwise with
+/// the checkpoint.
+///
+/// Otherwise the rows of the year are deleted before being loaded again, which also cleans up
+/// what a previous run might have left behind when it died half way through the year. When the
+/// year was stored before, the differences with the new rows are recorded into the
+/// 'thai_population_changes' audit table.
+///
+/// # Parameters
+///
+/// * `conn`: The shared database connection.
+/// * `year`: The Gregorian year to load.
+/// * `progress`: The reporter receiving the bytes fetched and the outcome of every line.
+/// * `checkpoint`: The checkpoint shared by all years.
+/// * `incremental`: Whether the skip decision is taken from the warehouse.
+///
+/// # Returns
+///
+/// A `Result` which is:
+/// * `Ok(YearOutcome)` telling whether the year was loaded or skipped.
+/// * `Err(IngestionError)` if the year could not be fetched, loaded or checkpointed.
+fn load_year(
+    conn: &Mutex<Connection>,
+    year: i32,
+    progress: &ProgressReporter,
+    checkpoint: &Mutex<Checkpoint>,
+    incremental: bool,
+) -> Result<YearOutcome, IngestionError> {
+    let data = get_data_stat_by_year(year).map_err(IngestionError::Fetch)?;
+    progress.report(IngestionEvent::BytesFetched {
+        data_year: year,
+        bytes: data.len() as u64,
+    });
+    let hash = content_hash(data.as_bytes());
+    let unchanged = if incremental {
+        stored_content_hash(&conn.lock().unwrap(), year)?.as_deref() == Some(hash.as_str())
+    } else {
+        checkpoint.lock().unwrap().is_up_to_date(year, &hash)
     };
+    if unchanged {
+        return Ok(YearOutcome::Unchanged);
+    }
+    checkpoint
+        .lock()
+        .unwrap()
+        .advance(year, Stage::Fetched, &hash, 0)?;
+
+    let mut rows = vec![];
+    for (index, line) in data.split('\n').enumerate() {
+        let _span = info_span!("row", data_year = year, line_number = index + 1).entered();
+        match parse_line(line) {
+            Ok(row) => {
+                // Rows are partitioned by the year of their file, which should match `yymm`
+                match row.year_month() {
+                    Ok(month) if month.year != year => {
+                        warn!(yymm = %row.yymm, "Line belongs to another year than its file")
+                    }
+                    Ok(_) => {}
+                    Err(e) => warn!(error = %e, "Line without a valid yymm"),
+                }
+                progress.report(IngestionEvent::RowParsed { data_year: year });
+                rows.push((index + 1, row));
+            }
+            Err(e) => {
+                warn!(error = %e, "Rejected line");
+                progress.report(IngestionEvent::RowRejected {
+                    data_year: year,
+                    reason: e.to_string(),
+                });
+            }
+        }
+    }
+    checkpoint
+        .lock()
+        .unwrap()
+        .advance(year, Stage::Parsed, &hash, rows.len() as u64)?;
+
+    let mut loaded = 0;
+    {
+        let conn = conn.lock().unwrap();
+        let previous_rows = snapshot_year(&conn, year)?;
+        delete_year(&conn, year)?;
+        for (line_number, row) in &rows {
+            let _span = info_span!("row", data_year = year, line_number).entered();
+            match insert_row(&conn, row, year) {
+                Ok(()) => {
+                    loaded += 1;
+                    progress.report(IngestionEvent::RowInserted { data_year: year });
+                }
+                Err(e) => {
+                    warn!(error = %e, "Rejected line");
+                    progress.report(IngestionEvent::RowRejected {
+                        data_year: year,
+                        reason: e.to_string(),
+                    });
+                }
+            }
+        }
+        record_source(&conn, year, &hash, loaded)?;
 
-    // Generate an SQL insert statement and execute it against the database connection
-    let insert_sql = generate_insert_sql_given_row_struct(year, &population_row);
-    conn.execute(&insert_sql, [])?;
+        if previous_rows > 0 {
+            let changes = record_year_changes(&conn, year)?;
+            if changes > 0 {
+                info!(changes, "Source file was revised since the last load");
+            }
+        } else {
+            drop_year_snapshot(&conn)?;
+        }
+    }
+    checkpoint
+        .lock()
+        .unwrap()
+        .advance(year, Stage::Loaded, &hash, loaded)?;
 
-    // Return success message
-    Ok("Updated population".to_string())
+    Ok(YearOutcome::Loaded(loaded))
 }
 
 /// Spawns a new thread to update population data for a given year.
@@ -138,42 +407,58 @@ fn update_row(conn: &Connection, line: &str, year: i32) -> Result<String, Ingest
 ///
 /// * `conn`: A reference to an `Arc<Mutex<Connection>>` containing the database connection.
 /// * `year`: An `i32` representing the year for which to update population data.
+/// * `progress`: The reporter receiving the bytes fetched and the outcome of every line.
+/// * `checkpoint`: The checkpoint shared by all years.
+/// * `incremental`: Whether the skip decision is taken from the warehouse.
 ///
 /// # Returns
 ///
 /// A `JoinHandle<()>` representing the handle to the spawned thread. The thread will update
 /// the population data for the specified year and exit once completed.
-fn update_population(conn: &Arc<Mutex<Connection>>, year: i32) -> JoinHandle<()> {
-    let conn_clone = Arc::clone(&conn);
+fn update_population(
+    conn: &Arc<Mutex<Connection>>,
+    year: i32,
+    progress: &Arc<ProgressReporter>,
+    checkpoint: &Arc<Mutex<Checkpoint>>,
+    incremental: bool,
+) -> JoinHandle<()> {
+    let conn_clone = Arc::clone(conn);
+    let progress = Arc::clone(progress);
+    let checkpoint = Arc::clone(checkpoint);
     let handle = thread::spawn(move || {
-        if let Ok(data) = get_data_stat_by_year(year) {
-            let data_lines: Vec<_> = data.split('\n').collect();
-            let mut thread_handles = vec![];
-
-            for line in data_lines {
-                let conn_inner = Arc::clone(&conn_clone);
-                let line = line.to_string();
-                let handle = thread::spawn(move || {
-                    let conn = conn_inner.lock().unwrap();
-                    update_row(&conn, &line, year).ok();
-                });
-                thread_handles.push(handle);
+        let _span = info_span!("year", data_year = year).entered();
+        match load_year(&conn_clone, year, &progress, &checkpoint, incremental) {
+            Ok(YearOutcome::Loaded(rows)) => {
+                info!(rows, "Year loaded");
+                progress.report(IngestionEvent::YearFinished { data_year: year });
             }
-
-            for handle in thread_handles {
-                handle.join().unwrap();
+            Ok(YearOutcome::Unchanged) => {
+                info!("Year unchanged since the last run, skipping");
+                progress.report(IngestionEvent::YearSkipped { data_year: year });
+            }
+            Err(e) => {
+                error!(error = %e, "Year failed");
+                progress.report(IngestionEvent::YearFailed {
+                    data_year: year,
+                    error: e.to_string(),
+                });
             }
         }
     });
     handle
 }
+
 /// Executes the main ingestion process using multithreading.
 ///
 /// This function performs the following steps:
-/// 1. Creates an in-memory DuckDB table.
-/// 2. Initiates population data updates for years 1993 to 2023 using multiple threads.
+/// 1. Opens the persistent DuckDB warehouse, applies its pending migrations and loads the
+///    checkpoint of the previous run.
+/// 2. Initiates population data updates for years 1993 to 2025 using multiple threads,
+///    skipping the years which were already loaded from the same content.
 /// 3. Waits for all update threads to complete.
-/// 4. Writes the collected data into Hive partitions.
+/// 4. Writes the collected data into Hive partitions, only rewriting the partitions of the
+///    years loaded by this run with `--incremental`.
+/// 5. Writes a JSON summary and a profiling report of the run next to the dataset.
 ///
 /// # Returns
 ///
@@ -182,20 +467,66 @@ fn update_population(conn: &Arc<Mutex<Connection>>, year: i32) -> JoinHandle<()>
 /// * `Err(IngestionError)` if any step in the process fails, where `IngestionError`
 ///   is a custom error type that encapsulates various potential error scenarios.
 fn main() -> Result<(), IngestionError> {
+    let cli = Cli::parse();
+    let log_config = LogConfig::from_env().map_err(IngestionError::Config)?;
+    let _log_guard = init_tracing(&log_config)?;
+    let metrics_config = MetricsConfig::from_env();
+    if let Some(addr) = &metrics_config.addr {
+        metrics().serve(addr)?;
+        info!(addr = %addr, "Serving metrics on /metrics");
+    }
+    // Open the Duckdb warehouse, keeping the years loaded by previous runs
+    let conn = open_warehouse()?;
+    match cli.command {
+        Some(Command::Migrations) => return show_migrations(&conn),
+        Some(Command::Serve { addr, cache_ttl }) => {
+            migrate(&conn, &WAREHOUSE_MIGRATIONS)?;
+            ApiServer::new(conn, Duration::from_secs(cache_ttl))?.serve(&addr)?;
+            return Ok(());
+        }
+        Some(Command::Repl { hive }) => {
+            let conn = match hive {
+                Some(root) => {
+                    let export = Connection::open_in_memory()?;
+                    attach_hive_export(&export, &root)?;
+                    export
+                }
+                None => {
+                    migrate(&conn, &WAREHOUSE_MIGRATIONS)?;
+                    conn
+                }
+            };
+            Repl::new(conn)?.run(Path::new(HISTORY_PATH))?;
+            return Ok(());
+        }
+        Some(Command::Project { horizon, holdout }) => {
+            migrate(&conn, &WAREHOUSE_MIGRATIONS)?;
+            return project(&conn, horizon, holdout);
+        }
+        None => {}
+    }
     println!("Run ingestion - Multithreading");
-    // Create a Duckdb table
-    let conn = Connection::open_in_memory()?;
-    create_duck_db_table(&conn)?;
+    migrate(&conn, &WAREHOUSE_MIGRATIONS)?;
     let conn = Arc::new(Mutex::new(conn));
+    let checkpoint = Arc::new(Mutex::new(Checkpoint::load(Path::new(CHECKPOINT_PATH))?));
 
     // Initial year
-    let start_year = 1993;
+    let start_year = FIRST_DATA_YEAR;
     let end_year = 2025;
 
+    let progress = Arc::new(
+        ProgressReporter::new("multithread")
+            .with_listener(TerminalProgress::new())
+            .with_listener(MetricsListener),
+    );
+    progress.report(IngestionEvent::YearsDiscovered(
+        (start_year..=end_year).collect(),
+    ));
+
     let mut handles = vec![];
     for year in start_year..=end_year {
         let conn_clone = Arc::clone(&conn);
-        let handle = update_population(&conn_clone, year);
+        let handle = update_population(&conn_clone, year, &progress, &checkpoint, cli.incremental);
         handles.push(handle);
     }
     for handle in handles {
@@ -206,22 +537,97 @@ fn main() -> Result<(), IngestionError> {
         .expect("Failed to unwrap Arc")
         .into_inner()
         .unwrap();
-    write_into_hive_partition(&conn)?;
+    let mismatches = reconcile(&conn)?;
+    for mismatch in &mismatches {
+        warn!(
+            data_year = mismatch.data_year,
+            level = %mismatch.level,
+            area_code = %mismatch.area_code,
+            column = %mismatch.column,
+            published = mismatch.published,
+            summed = mismatch.summed,
+            "Aggregate line does not match the sum of its children"
+        );
+    }
+    if cli.separate_aggregates {
+        let moved = separate_aggregates(&conn)?;
+        info!(moved, "Moved aggregate lines into thai_population_aggregates");
+    }
+    let versions = build_dim_admin_area(&conn)?;
+    info!(versions, "Rebuilt dim_admin_area");
+    let reference_year = validate_data_year(cli.reference_year.unwrap_or(end_year))
+        .map_err(|e| IngestionError::Config(e.to_string()))?;
+    build_harmonised_view(&conn, &Crosswalk::load(&cli.crosswalk)?, reference_year)?;
+    info!(reference_year, "Rebuilt thai_population_harmonised");
+    let indicators = build_indicators(&conn)?;
+    info!(rows = indicators, "Rebuilt thai_population_indicators");
+    let anomalies = detect_anomalies(&conn)?;
+    for anomaly in &anomalies {
+        warn!(
+            data_year = anomaly.data_year,
+            cc_code = anomaly.cc_code,
+            kind = %anomaly.kind,
+            value = anomaly.value,
+            score = anomaly.score,
+            "Unusual change since the previous year"
+        );
+    }
+    if cli.incremental {
+        let loaded_years = progress
+            .summary()
+            .years
+            .into_iter()
+            .filter(|(_, year)| year.status == YearStatus::Finished)
+            .map(|(year, _)| year)
+            .collect::<Vec<i32>>();
+        write_years_into_hive_partition(&conn, &loaded_years)?;
+    } else {
+        write_into_hive_partition(&conn)?;
+    }
+    write_indicators_into_hive_partition(&conn)?;
+    checkpoint.lock().unwrap().mark_exported()?;
+
+    if let Some(path) = &metrics_config.textfile {
+        metrics().write_textfile(path)?;
+    }
+
+    let summary = progress.summary();
+    summary.write_json(Path::new(SUMMARY_PATH))?;
+    println!(
+        "Inserted {} rows ({} rejected) from {}/{} years ({} unchanged) in {:.1}s, summary written to {}",
+        summary.rows_inserted,
+        summary.rows_rejected,
+        summary.years_finished,
+        summary.years_discovered,
+        summary.years_skipped,
+        summary.elapsed_seconds,
+        SUMMARY_PATH
+    );
+    ProfileReport::build(&conn, &summary, &anomalies)?.write(&cli.profile_report)?;
+    println!("Profiling report written to {}", cli.profile_report.display());
+    if !mismatches.is_empty() {
+        println!(
+            "{} count(s) not matching their child lines, see thai_population_reconciliation",
+            mismatches.len()
+        );
+    }
+    if !anomalies.is_empty() {
+        println!(
+            "{} unusual year(s) flagged into thai_population_anomalies",
+            anomalies.len()
+        );
+    }
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use databases::duckdb_functions::{
+        create_duck_db_table, ensure_changes_table, ensure_source_table,
+    };
     use duckdb::Connection;
 
-    #[test]
-    fn test_convert_to_thai_year() {
-        assert_eq!(convert_to_thai_year(2000), 43);
-        assert_eq!(convert_to_thai_year(2023), 66);
-        assert_eq!(convert_to_thai_year(1993), 36);
-    }
-
     #[test]
     fn test_extract_row() {
         let row = "value1|value2|value3";
@@ -230,23 +636,23 @@ mod tests {
     }
 
     #[test]
-    fn test_update_row_success() {
+    fn test_parse_and_insert_row_success() {
         let conn = Connection::open_in_memory().expect("Failed to create connection");
         // Assuming `create_duck_db_table` creates the required table structure
         create_duck_db_table(&conn).expect("Failed to create table");
         let year = 2023;
-        let line = "|2024|001|Description|RC01|Region Description|CCA01|CCAATT Desc|CCAMM01|CCAATTMM Desc|1234|5678|6912|345|";
+        let line = "|6612|001|Description|0101|Region Description|010100|CCAATT Desc|01010100|CCAATTMM Desc|1234|5678|6912|345|";
 
         // Mock PopulationRow parse and SQL generator for the test
         let row_vec = vec![
             "2023",
             "002",
             "Description",
-            "RC01",
+            "0101",
             "Region Description",
-            "CCA01",
+            "010100",
             "CCAATT Desc",
-            "CCAMM01",
+            "01010100",
             "CCAATTMM Desc",
             "1234",
             "5678",
@@ -259,12 +665,104 @@ mod tests {
         let parse_result = PopulationRow::parse(row_vec);
         assert!(parse_result.is_ok());
 
-        let sql = generate_insert_sql_given_row_struct(year, &parse_result.unwrap());
-        assert!(conn.execute(&sql, []).is_ok());
+        assert!(insert_population_row(&conn, year, &parse_result.unwrap()).is_ok());
+
+        let population_row = parse_line(line).expect("Failed to parse line");
+        assert_eq!(population_row.cc_code.to_string(), "01");
+
+        assert!(insert_row(&conn, &population_row, year).is_ok());
+    }
+
+    #[test]
+    fn test_stored_content_hash_follows_recorded_source() {
+        let conn = Connection::open_in_memory().expect("Failed to create connection");
+        ensure_source_table(&conn).expect("Failed to create table");
+        assert_eq!(stored_content_hash(&conn, 2023).unwrap(), None);
+
+        record_source(&conn, 2023, "aaa", 10).unwrap();
+        record_source(&conn, 2023, "bbb", 11).unwrap();
+        assert_eq!(
+            stored_content_hash(&conn, 2023).unwrap(),
+            Some("bbb".to_string())
+        );
+    }
+
+    #[test]
+    fn test_record_year_changes_between_loads() {
+        let conn = Connection::open_in_memory().expect("Failed to create connection");
+        create_duck_db_table(&conn).expect("Failed to create table");
+        ensure_changes_table(&conn).expect("Failed to create table");
+        let year = 2023;
+        let line = |cc_code: &str, male: i32| {
+            format!(
+                "|6612|{}|Desc|0|Region|0|CCAATT|0|CCAATTMM|{}|5|{}|3|",
+                cc_code,
+                male,
+                male + 5
+            )
+        };
+        for row in [line("10", 1), line("11", 2)] {
+            insert_row(&conn, &parse_line(&row).unwrap(), year).unwrap();
+        }
+
+        assert_eq!(snapshot_year(&conn, year).unwrap(), 2);
+        delete_year(&conn, year).unwrap();
+        // 10 is unchanged, 11 is revised, 12 is new
+        for row in [line("10", 1), line("11", 4), line("12", 6)] {
+            insert_row(&conn, &parse_line(&row).unwrap(), year).unwrap();
+        }
+        assert_eq!(record_year_changes(&conn, year).unwrap(), 2);
 
-        let result = update_row(&conn, line, year);
+        let mut stmt = conn
+            .prepare(
+                "SELECT cc_code, change_type, old_male, new_male
+                FROM thai_population_changes ORDER BY cc_code",
+            )
+            .unwrap();
+        let changes = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, i32>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<i32>>(2)?,
+                    row.get::<_, Option<i32>>(3)?,
+                ))
+            })
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            changes,
+            vec![
+                (11, "changed".to_string(), Some(2), Some(4)),
+                (12, "inserted".to_string(), None, Some(6)),
+            ]
+        );
+    }
 
-        assert!(result.is_ok());
-        assert_eq!(result.unwrap(), "Updated population");
+    #[test]
+    fn test_warehouse_migrations_create_every_table() {
+        let conn = Connection::open_in_memory().expect("Failed to create connection");
+        assert_eq!(
+            migrate(&conn, &WAREHOUSE_MIGRATIONS).unwrap(),
+            vec![1, 2, 3]
+        );
+        for table in [
+            "thai_population",
+            "thai_population_sources",
+            "thai_population_changes",
+        ] {
+            assert!(conn
+                .execute(&format!("SELECT * FROM {}", table), [])
+                .is_ok());
+        }
+        let status = migration_status(&conn, &WAREHOUSE_MIGRATIONS).unwrap();
+        assert!(status.iter().all(|migration| migration.applied));
+    }
+
+    #[test]
+    fn test_parse_line_rejects_wrong_field_count() {
+        let result = parse_line("|2024|001|Description|");
+        assert!(matches!(result, Err(IngestionError::Parse(_))));
     }
 }
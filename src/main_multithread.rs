@@ -1,15 +1,17 @@
 mod databases;
-mod parsers;
 use databases::duckdb_functions::{
-    create_duck_db_table, generate_insert_sql_given_row_struct, write_into_hive_partition,
+    create_duck_db_table, insert_population_row, write_into_hive_partition,
 };
 use duckdb::{Connection, Error as DuckDBError, Result};
 
 use reqwest::Error as RequestwestError;
+use rust_hive::calendar::{to_short_buddhist_year, FIRST_DATA_YEAR};
+use rust_hive::logging::{init_tracing, LogConfig};
 use rust_hive::parsers::population::PopulationRow;
 use thiserror::Error;
 use std::sync::{Arc, Mutex};
 use std::thread;
+use tracing::warn;
 
 
 // Custom error handling
@@ -23,26 +25,12 @@ enum IngestionError {
     Requestwest(#[from] RequestwestError),
     #[error("Parse error: {0}")]
     Parse(String),
-}
-
-/// Converts a Gregorian year to a Thai year.
-///3
-/// This function takes an integer representing a Gregorian year and returns the corresponding Thai year.
-/// The conversion is based on the Thai calendar system, which starts from the year 2500.
-///
-/// # Parameters
-///
-/// * `year` - An integer representing the Gregorian year.
-///
-/// # Returns
-///
-/// An integer representing the corresponding Thai year.
-fn convert_to_thai_year(year: i32) -> i32 {
-    year + 543 - 2500
+    #[error("Configuration error: {0}")]
+    Config(String),
 }
 
 fn get_data_stat_by_year(year: i32) -> Result<String, String> {
-    let thai_year = convert_to_thai_year(year);
+    let thai_year = to_short_buddhist_year(year);
     let url = format!(
         "https://stat.bora.dopa.go.th/new_stat/file/{}/stat_c{}.txt",
         thai_year, thai_year
@@ -64,7 +52,6 @@ fn get_data_stat_by_year(year: i32) -> Result<String, String> {
 
 fn extract_row(row: &str) -> Vec<String> {
     row.split('|')
-        .into_iter()
         .map(|value| value.to_string())
         .collect::<Vec<String>>()
 }
@@ -94,15 +81,16 @@ fn update_row(conn: &Connection, line: &str, year: i32) -> Result<String, Ingest
         Err(e) => return Err(IngestionError::Parse(e)),
     };
 
-    // Generate an SQL insert statement and execute it against the database connection
-    let insert_sql = generate_insert_sql_given_row_struct(year, &population_row);
-    conn.execute(&insert_sql, [])?;
+    // Insert the row, binding every column of the struct
+    insert_population_row(conn, year, &population_row)?;
 
     // Return success message
     Ok("Updated population".to_string())
 }
 
 fn main() -> Result<(), IngestionError> {
+    let log_config = LogConfig::from_env().map_err(IngestionError::Config)?;
+    let _log_guard = init_tracing(&log_config)?;
     println!("Run ingestion - Multithreading");
     // Create a Duckdb table
     let conn = Connection::open_in_memory()?;
@@ -110,7 +98,7 @@ fn main() -> Result<(), IngestionError> {
     let conn = Arc::new(Mutex::new(conn));
 
     // Initial year
-    let start_year = 1993;
+    let start_year = FIRST_DATA_YEAR;
     let end_year = 2023;
 
     let mut handles = vec![];
@@ -126,7 +114,9 @@ fn main() -> Result<(), IngestionError> {
                     let line = line.to_string();
                     let handle = thread::spawn(move || {
                         let conn = conn_inner.lock().unwrap();
-                        update_row(&conn, &line, year).ok();
+                        if let Err(e) = update_row(&conn, &line, year) {
+                            warn!(data_year = year, error = %e, "Rejected line");
+                        }
                     });
                     thread_handles.push(handle);
                 }
@@ -1,20 +1,32 @@
 mod databases;
-mod parsers;
 
-use std::sync::Arc;
-use tokio::sync::Mutex;
 use databases::duckdb_functions::{
-    create_duck_db_table, generate_insert_sql_given_row_struct, write_into_hive_partition,
+    create_duck_db_table, insert_population_row, write_into_hive_partition,
 };
 use duckdb::{Connection, Error as DuckDBError, Result};
+use std::sync::Arc;
 
-use reqwest::Error as RequestwestError;
+use futures::StreamExt;
 use reqwest::Client;
+use reqwest::Error as RequestwestError;
+use rust_hive::calendar::{to_short_buddhist_year, FIRST_DATA_YEAR};
+use rust_hive::logging::{init_tracing, LogConfig};
+use rust_hive::metrics::{metrics, MetricsConfig, MetricsListener};
 use rust_hive::parsers::population::PopulationRow;
+use rust_hive::progress::{IngestionEvent, ProgressReporter, TerminalProgress, SUMMARY_PATH};
+use std::path::Path;
+use std::time::Instant;
 use thiserror::Error;
-use tokio::task::JoinError;
-use futures::future::join_all;
+use tokio::sync::{mpsc, Semaphore};
+use tokio::task::{JoinError, JoinSet};
+use tracing::field::Empty;
+use tracing::{debug_span, error, info, info_span, instrument, warn, Span};
+
+/// Maximum number of years fetched from DOPA at the same time.
+const MAX_CONCURRENT_FETCHES: usize = 8;
 
+/// Number of lines buffered between the fetchers and the DuckDB writer.
+const WRITER_CHANNEL_CAPACITY: usize = 1024;
 
 // Custom error handling
 #[derive(Error, Debug)]
@@ -27,51 +39,135 @@ enum IngestionError {
     Requestwest(#[from] RequestwestError),
     #[error("Join error: {0}")]
     Join(#[from] JoinError),
+    #[error("Fail request with HTTP code: {0}")]
+    Http(u16),
+    #[error("DuckDB writer stopped before all rows were sent")]
+    WriterClosed,
     #[error("Parse error: {0}")]
     Parse(String),
+    #[error("Configuration error: {0}")]
+    Config(String),
 }
 
-/// Converts a Gregorian year to a Thai year.
-///3
-/// This function takes an integer representing a Gregorian year and returns the corresponding Thai year.
-/// The conversion is based on the Thai calendar system, which starts from the year 2500.
+/// A raw line of a DOPA statistic file, tagged with the year and line number it belongs to.
+type YearLine = (i32, usize, String);
+
+/// Splits every complete line out of `buffer`, leaving a trailing partial line in place.
+///
+/// Response bodies arrive in arbitrary chunks, so a line may be cut in half at a chunk
+/// boundary. The unfinished tail stays in the buffer until the next chunk completes it.
 ///
 /// # Parameters
 ///
-/// * `year` - An integer representing the Gregorian year.
+/// * `buffer` - Bytes received so far which have not been turned into lines yet.
 ///
 /// # Returns
 ///
-/// An integer representing the corresponding Thai year.
-fn convert_to_thai_year(year: i32) -> i32 {
-    year + 543 - 2500
+/// The complete lines found in the buffer, without their line terminators.
+fn drain_complete_lines(buffer: &mut Vec<u8>) -> Vec<String> {
+    let mut lines = vec![];
+    while let Some(position) = buffer.iter().position(|&byte| byte == b'\n') {
+        let line: Vec<u8> = buffer.drain(..=position).collect();
+        lines.push(String::from_utf8_lossy(&line).to_string());
+    }
+    lines
 }
 
-async fn get_data_stat_by_year(year: i32) -> Result<String, String> {
-    let client = Client::new();
-    let thai_year = convert_to_thai_year(year);
+/// Streams the statistic file of a given year and forwards its lines to the writer.
+///
+/// The body is consumed chunk by chunk instead of being collected into a single string,
+/// so a year is handed over to DuckDB while it is still being downloaded.
+///
+/// # Parameters
+///
+/// * `client` - A shared `reqwest` client.
+/// * `year` - The Gregorian year to fetch.
+/// * `sender` - The channel feeding the DuckDB writer task.
+/// * `progress` - The reporter receiving the size of every chunk.
+///
+/// # Returns
+///
+/// A `Result` which is:
+/// * `Ok` with the number of lines sent to the writer.
+/// * `Err(IngestionError)` if the request fails, returns a non-2xx status code or the writer is gone.
+#[instrument(name = "fetch", skip_all, fields(data_year = year, thai_year = Empty, url = Empty))]
+async fn stream_data_stat_by_year(
+    client: &Client,
+    year: i32,
+    sender: &mpsc::Sender<YearLine>,
+    progress: &ProgressReporter,
+) -> Result<usize, IngestionError> {
+    let thai_year = to_short_buddhist_year(year);
     let url = format!(
         "https://stat.bora.dopa.go.th/new_stat/file/{}/stat_c{}.txt",
         thai_year, thai_year
     );
-    let mut result = String::new();
-    if let Ok(response) = client.get(&url).send().await {
-        if response.status().as_u16()/ 100 != 2 {
-            return Err(format!(
-                "Fail request with HTTP code: {:?}",
-                response.status().as_u16()
-            ));
+    Span::current()
+        .record("thai_year", thai_year)
+        .record("url", url.as_str());
+
+    let started = Instant::now();
+    let response = match client.get(&url).send().await {
+        Ok(response) => response,
+        Err(e) => {
+            metrics().observe_http(None, started.elapsed());
+            return Err(e.into());
         }
-        result = response.text().await.ok().unwrap();
+    };
+    metrics().observe_http(Some(response.status().as_u16()), started.elapsed());
+    info!(status = response.status().as_u16(), "Received response");
+    if !response.status().is_success() {
+        return Err(IngestionError::Http(response.status().as_u16()));
     }
 
-    Ok(result.trim_matches(|c| c == ' ' || c == '\n').to_string())
+    let mut body = response.bytes_stream();
+    let mut buffer = Vec::new();
+    let mut sent = 0;
+    let mut line_number = 0;
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk?;
+        progress.report(IngestionEvent::BytesFetched {
+            data_year: year,
+            bytes: chunk.len() as u64,
+        });
+        buffer.extend_from_slice(&chunk);
+        for line in drain_complete_lines(&mut buffer) {
+            line_number += 1;
+            sent += send_line(sender, year, line_number, line).await?;
+        }
+    }
+    // The last line of a file usually has no trailing newline
+    if !buffer.is_empty() {
+        let line = String::from_utf8_lossy(&buffer).to_string();
+        sent += send_line(sender, year, line_number + 1, line).await?;
+    }
+
+    Ok(sent)
 }
 
+/// Sends a non-blank line to the writer task.
+///
+/// # Returns
+///
+/// The number of lines sent, i.e. `0` for blank lines and `1` otherwise.
+async fn send_line(
+    sender: &mpsc::Sender<YearLine>,
+    year: i32,
+    line_number: usize,
+    line: String,
+) -> Result<usize, IngestionError> {
+    if line.trim().is_empty() {
+        return Ok(0);
+    }
+    sender
+        .send((year, line_number, line))
+        .await
+        .map_err(|_| IngestionError::WriterClosed)?;
+    Ok(1)
+}
 
 fn extract_row(row: &str) -> Vec<String> {
     row.split('|')
-        .into_iter()
         .map(|value| value.to_string())
         .collect::<Vec<String>>()
 }
@@ -93,52 +189,185 @@ fn extract_row(row: &str) -> Vec<String> {
 /// A `Result` which is:
 /// * `Ok` with a `String` "Updated population" if the operation was successful.
 /// * `Err` with a boxed dynamic `Error` if any step in the process fails.
-async fn a_update_row(conn:  Arc<Mutex<&Connection>>, line: &str, year: i32) -> Result<String, IngestionError> {
+fn update_row(conn: &Connection, line: &str, year: i32) -> Result<String, IngestionError> {
     // Extract fields from the line and convert them into a PopulationRow struct
     let extracted = extract_row(line.trim_matches(|c| ['|', ' ', '\n', '\r'].contains(&c)));
-    let population_row = match PopulationRow::parse(extracted) {
+    let population_row = match debug_span!("parse").in_scope(|| PopulationRow::parse(extracted)) {
         Ok(row) => row,
         Err(e) => return Err(IngestionError::Parse(e)),
     };
 
-    // Generate an SQL insert statement
-    let insert_sql = generate_insert_sql_given_row_struct(year, &population_row);
-
-    // Execute the SQL asynchronously
-    let conn = conn.lock().await;
-    conn.execute(&insert_sql, []).map_err(|e| IngestionError::DuckDB(e))?;
-
+    let started = Instant::now();
+    debug_span!("insert").in_scope(|| insert_population_row(conn, year, &population_row))?;
+    metrics().observe_insert(started.elapsed());
 
     // Return success message
     Ok("Updated population".to_string())
 }
 
+/// Records the outcome of `update_row` for a single line.
+///
+/// A line failing to parse is only rejected, while a line failing to insert was still parsed.
+fn report_row_outcome(
+    progress: &ProgressReporter,
+    year: i32,
+    outcome: Result<String, IngestionError>,
+) {
+    match outcome {
+        Ok(_) => {
+            progress.report(IngestionEvent::RowParsed { data_year: year });
+            progress.report(IngestionEvent::RowInserted { data_year: year });
+        }
+        Err(IngestionError::Parse(reason)) => {
+            progress.report(IngestionEvent::RowRejected {
+                data_year: year,
+                reason,
+            });
+        }
+        Err(e) => {
+            progress.report(IngestionEvent::RowParsed { data_year: year });
+            progress.report(IngestionEvent::RowRejected {
+                data_year: year,
+                reason: e.to_string(),
+            });
+        }
+    }
+}
+
+/// Drains the writer channel into DuckDB.
+///
+/// This is the only place touching the connection while years are being fetched. It runs
+/// on the blocking thread pool, so DuckDB calls never stall the async workers.
+///
+/// # Parameters
+///
+/// * `conn` - The DuckDB connection, owned by the writer until the channel is closed.
+/// * `receiver` - The receiving half of the channel fed by the fetch tasks.
+/// * `progress` - The reporter receiving the outcome of every line.
+///
+/// # Returns
+///
+/// The connection, handed back once every sender has been dropped.
+fn write_rows(
+    conn: Connection,
+    mut receiver: mpsc::Receiver<YearLine>,
+    progress: Arc<ProgressReporter>,
+) -> Connection {
+    while let Some((year, line_number, line)) = receiver.blocking_recv() {
+        let _span = info_span!("row", data_year = year, line_number).entered();
+        let outcome = update_row(&conn, &line, year);
+        if let Err(e) = &outcome {
+            warn!(error = %e, "Rejected line");
+        }
+        report_row_outcome(&progress, year, outcome);
+    }
+    conn
+}
+
 #[tokio::main(flavor = "multi_thread", worker_threads = 8)]
 async fn main() -> Result<(), IngestionError> {
+    let log_config = LogConfig::from_env().map_err(IngestionError::Config)?;
+    let _log_guard = init_tracing(&log_config)?;
+    let metrics_config = MetricsConfig::from_env();
+    if let Some(addr) = &metrics_config.addr {
+        metrics().serve(addr)?;
+        info!(addr = %addr, "Serving metrics on /metrics");
+    }
     println!("Run ingestion - Tokio");
     // Create a Duckdb table
     let conn = Connection::open_in_memory()?;
     create_duck_db_table(&conn)?;
 
     // Initial year
-    let mut year = 1993;
-    // Ingest data and update the database in batches of 1000 rows per transactions
-    // conn.execute("BEGIN TRANSACTION", [])?;
-    while let Ok(data) = get_data_stat_by_year(year).await {
-        let futures = data
-            .split("\n")
-            .into_iter()
-            .map(|line| {
-                let conn = Arc::new(Mutex::new(&conn));  // Make connection Arc for use in async block
-                async move {
-                    a_update_row(conn, line, year).await
-                }
-            }).collect::<Vec<_>>();
-
-        let _results = join_all(futures).await;
-        year += 1;
+    let start_year = FIRST_DATA_YEAR;
+    let end_year = 2025;
+
+    let progress = Arc::new(
+        ProgressReporter::new("tokio")
+            .with_listener(TerminalProgress::new())
+            .with_listener(MetricsListener),
+    );
+    progress.report(IngestionEvent::YearsDiscovered(
+        (start_year..=end_year).collect(),
+    ));
+
+    let (sender, receiver) = mpsc::channel::<YearLine>(WRITER_CHANNEL_CAPACITY);
+    let writer_progress = Arc::clone(&progress);
+    let writer = tokio::task::spawn_blocking(move || write_rows(conn, receiver, writer_progress));
+
+    let client = Client::new();
+    let semaphore = Arc::new(Semaphore::new(MAX_CONCURRENT_FETCHES));
+    let mut fetches = JoinSet::new();
+    for year in start_year..=end_year {
+        let client = client.clone();
+        let sender = sender.clone();
+        let semaphore = Arc::clone(&semaphore);
+        let progress = Arc::clone(&progress);
+        fetches.spawn(async move {
+            let _permit = semaphore
+                .acquire_owned()
+                .await
+                .expect("Semaphore is never closed");
+            (
+                year,
+                stream_data_stat_by_year(&client, year, &sender, &progress).await,
+            )
+        });
+    }
+    // Only the fetch tasks hold senders now, so the writer stops once they are done
+    drop(sender);
+
+    while let Some(fetched) = fetches.join_next().await {
+        match fetched? {
+            (year, Ok(lines)) => {
+                info!(data_year = year, lines, "Year fetched");
+                progress.report(IngestionEvent::YearFinished { data_year: year });
+            }
+            (year, Err(e)) => {
+                error!(data_year = year, error = %e, "Year failed");
+                progress.report(IngestionEvent::YearFailed {
+                    data_year: year,
+                    error: e.to_string(),
+                });
+            }
+        }
+    }
+
+    let conn = writer.await?;
+    tokio::task::spawn_blocking(move || write_into_hive_partition(&conn)).await??;
+
+    if let Some(path) = &metrics_config.textfile {
+        metrics().write_textfile(path)?;
     }
-    write_into_hive_partition(&conn)?;
-    
+
+    let summary = progress.summary();
+    summary.write_json(Path::new(SUMMARY_PATH))?;
+    println!(
+        "Inserted {} rows ({} rejected) from {}/{} years in {:.1}s, summary written to {}",
+        summary.rows_inserted,
+        summary.rows_rejected,
+        summary.years_finished,
+        summary.years_discovered,
+        summary.elapsed_seconds,
+        SUMMARY_PATH
+    );
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_drain_complete_lines_keeps_partial_tail() {
+        let mut buffer = b"6612|10|a\r\n6612|11|b\n6612|1".to_vec();
+        let lines = drain_complete_lines(&mut buffer);
+        assert_eq!(lines, vec!["6612|10|a\r\n", "6612|11|b\n"]);
+        assert_eq!(buffer, b"6612|1".to_vec());
+
+        buffer.extend_from_slice(b"2|c\n");
+        assert_eq!(drain_complete_lines(&mut buffer), vec!["6612|12|c\n"]);
+        assert!(buffer.is_empty());
+    }
+}
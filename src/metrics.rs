@@ -0,0 +1,375 @@
+use crate::progress::{IngestionEvent, ProgressListener};
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Environment variable holding the address of the `/metrics` endpoint, e.g. `127.0.0.1:9898`.
+pub const METRICS_ADDR_ENV: &str = "RUST_HIVE_METRICS_ADDR";
+/// Environment variable holding the path of a node_exporter textfile-collector file.
+pub const METRICS_TEXTFILE_ENV: &str = "RUST_HIVE_METRICS_TEXTFILE";
+
+const HTTP_BUCKETS: &[f64] = &[0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
+const INSERT_BUCKETS: &[f64] = &[0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5];
+const EXPORT_BUCKETS: &[f64] = &[0.5, 1.0, 5.0, 10.0, 30.0, 60.0, 300.0];
+
+/// Where the metrics of a run are published.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MetricsConfig {
+    pub addr: Option<String>,
+    pub textfile: Option<PathBuf>,
+}
+
+impl MetricsConfig {
+    /// Reads the configuration from `RUST_HIVE_METRICS_ADDR` and `RUST_HIVE_METRICS_TEXTFILE`.
+    ///
+    /// Both outputs are disabled when their variable is unset.
+    pub fn from_env() -> Self {
+        MetricsConfig {
+            addr: env::var(METRICS_ADDR_ENV).ok(),
+            textfile: env::var(METRICS_TEXTFILE_ENV).ok().map(PathBuf::from),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Histogram {
+    bounds: &'static [f64],
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Self {
+        Histogram {
+            bounds,
+            counts: vec![0; bounds.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, value: f64) {
+        for (bound, count) in self.bounds.iter().zip(self.counts.iter_mut()) {
+            if value <= *bound {
+                *count += 1;
+            }
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str) {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} histogram", name);
+        for (bound, count) in self.bounds.iter().zip(&self.counts) {
+            let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, count);
+        }
+        let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, self.count);
+        let _ = writeln!(out, "{}_sum {}", name, self.sum);
+        let _ = writeln!(out, "{}_count {}", name, self.count);
+    }
+}
+
+#[derive(Debug, Clone)]
+struct MetricsState {
+    http_responses: BTreeMap<String, u64>,
+    http_duration: Histogram,
+    rows_inserted: BTreeMap<i32, u64>,
+    rows_rejected: BTreeMap<&'static str, u64>,
+    insert_duration: Histogram,
+    export_duration: Histogram,
+    years_finished: u64,
+    years_skipped: u64,
+    years_failed: u64,
+    last_success_unix: Option<u64>,
+}
+
+/// Counters and histograms of the ingestion pipeline, rendered in the Prometheus text format.
+///
+/// A single process-wide instance is reachable through `metrics()`.
+#[derive(Debug)]
+pub struct Metrics {
+    state: Mutex<MetricsState>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Metrics {
+            state: Mutex::new(MetricsState {
+                http_responses: BTreeMap::new(),
+                http_duration: Histogram::new(HTTP_BUCKETS),
+                rows_inserted: BTreeMap::new(),
+                rows_rejected: BTreeMap::new(),
+                insert_duration: Histogram::new(INSERT_BUCKETS),
+                export_duration: Histogram::new(EXPORT_BUCKETS),
+                years_finished: 0,
+                years_skipped: 0,
+                years_failed: 0,
+                last_success_unix: None,
+            }),
+        }
+    }
+
+    /// Records a DOPA request, `status` being `None` when no response was received at all.
+    pub fn observe_http(&self, status: Option<u16>, elapsed: Duration) {
+        let mut state = self.state.lock().unwrap();
+        let status = status.map_or("error".to_string(), |code| code.to_string());
+        *state.http_responses.entry(status).or_default() += 1;
+        state.http_duration.observe(elapsed.as_secs_f64());
+    }
+
+    pub fn observe_insert(&self, elapsed: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.insert_duration.observe(elapsed.as_secs_f64());
+    }
+
+    pub fn observe_export(&self, elapsed: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.export_duration.observe(elapsed.as_secs_f64());
+        state.last_success_unix = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .map(|d| d.as_secs());
+    }
+
+    /// Renders every metric in the Prometheus/OpenMetrics text exposition format.
+    pub fn render(&self) -> String {
+        let state = self.state.lock().unwrap().clone();
+        let mut out = String::new();
+
+        let _ = writeln!(
+            out,
+            "# HELP rust_hive_http_responses_total Requests sent to DOPA by HTTP status code."
+        );
+        let _ = writeln!(out, "# TYPE rust_hive_http_responses_total counter");
+        for (status, count) in &state.http_responses {
+            let _ = writeln!(
+                out,
+                "rust_hive_http_responses_total{{status=\"{}\"}} {}",
+                status, count
+            );
+        }
+        state.http_duration.render(
+            &mut out,
+            "rust_hive_http_request_duration_seconds",
+            "Latency of DOPA requests.",
+        );
+
+        let _ = writeln!(
+            out,
+            "# HELP rust_hive_rows_inserted_total Rows inserted into thai_population by data year."
+        );
+        let _ = writeln!(out, "# TYPE rust_hive_rows_inserted_total counter");
+        for (year, count) in &state.rows_inserted {
+            let _ = writeln!(
+                out,
+                "rust_hive_rows_inserted_total{{data_year=\"{}\"}} {}",
+                year, count
+            );
+        }
+        let _ = writeln!(
+            out,
+            "# HELP rust_hive_rows_rejected_total Lines rejected by the rule they failed."
+        );
+        let _ = writeln!(out, "# TYPE rust_hive_rows_rejected_total counter");
+        for (rule, count) in &state.rows_rejected {
+            let _ = writeln!(
+                out,
+                "rust_hive_rows_rejected_total{{rule=\"{}\"}} {}",
+                rule, count
+            );
+        }
+        state.insert_duration.render(
+            &mut out,
+            "rust_hive_insert_duration_seconds",
+            "Latency of DuckDB row inserts.",
+        );
+        state.export_duration.render(
+            &mut out,
+            "rust_hive_export_duration_seconds",
+            "Duration of the Hive partition export.",
+        );
+
+        let _ = writeln!(
+            out,
+            "# HELP rust_hive_years_total Years processed by outcome."
+        );
+        let _ = writeln!(out, "# TYPE rust_hive_years_total counter");
+        let _ = writeln!(
+            out,
+            "rust_hive_years_total{{outcome=\"finished\"}} {}",
+            state.years_finished
+        );
+        let _ = writeln!(
+            out,
+            "rust_hive_years_total{{outcome=\"skipped\"}} {}",
+            state.years_skipped
+        );
+        let _ = writeln!(
+            out,
+            "rust_hive_years_total{{outcome=\"failed\"}} {}",
+            state.years_failed
+        );
+        if let Some(timestamp) = state.last_success_unix {
+            let _ = writeln!(
+                out,
+                "# HELP rust_hive_last_success_timestamp_seconds End of the last successful export."
+            );
+            let _ = writeln!(out, "# TYPE rust_hive_last_success_timestamp_seconds gauge");
+            let _ = writeln!(
+                out,
+                "rust_hive_last_success_timestamp_seconds {}",
+                timestamp
+            );
+        }
+        out
+    }
+
+    /// Writes the rendered metrics for the node_exporter textfile collector.
+    ///
+    /// The file is written next to its destination and renamed, so the collector never
+    /// scrapes a half written file.
+    pub fn write_textfile(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        let temporary = path.with_extension("prom.tmp");
+        fs::write(&temporary, self.render())?;
+        fs::rename(temporary, path)
+    }
+
+    /// Serves `GET /metrics` on `addr` from a background thread.
+    ///
+    /// The endpoint is meant for a local scraper, so it answers one request at a time.
+    pub fn serve(&'static self, addr: &str) -> io::Result<JoinHandle<()>> {
+        let listener = TcpListener::bind(addr)?;
+        Ok(thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut request_line = String::new();
+                if BufReader::new(&stream)
+                    .read_line(&mut request_line)
+                    .is_err()
+                {
+                    continue;
+                }
+                let response = if request_line.starts_with("GET /metrics ") {
+                    let body = self.render();
+                    format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    )
+                } else {
+                    "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                        .to_string()
+                };
+                let _ = stream.write_all(response.as_bytes());
+            }
+        }))
+    }
+}
+
+/// Maps the reason of a rejected line to the rule it broke.
+///
+/// Parse failures come from `PopulationRow::parse`, anything else failed while inserting.
+pub fn reject_rule(reason: &str) -> &'static str {
+    if reason.contains("correct number of fields") {
+        "field_count"
+    } else if reason.contains("Invalid ") && reason.contains(" code ") {
+        "invalid_code"
+    } else if reason.contains("parse integer")
+        || reason.contains("invalid digit")
+        || reason.contains("too large")
+        || reason.contains("too small")
+    {
+        "invalid_number"
+    } else {
+        "insert"
+    }
+}
+
+/// Returns the process-wide metrics.
+pub fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(Metrics::new)
+}
+
+/// Feeds the row and year counters of `metrics()` from the progress events of a run.
+pub struct MetricsListener;
+
+impl ProgressListener for MetricsListener {
+    fn on_event(&self, event: &IngestionEvent) {
+        let mut state = metrics().state.lock().unwrap();
+        match event {
+            IngestionEvent::RowInserted { data_year } => {
+                *state.rows_inserted.entry(*data_year).or_default() += 1;
+            }
+            IngestionEvent::RowRejected { reason, .. } => {
+                *state.rows_rejected.entry(reject_rule(reason)).or_default() += 1;
+            }
+            IngestionEvent::YearFinished { .. } => state.years_finished += 1,
+            IngestionEvent::YearSkipped { .. } => state.years_skipped += 1,
+            IngestionEvent::YearFailed { .. } => state.years_failed += 1,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reject_rule() {
+        assert_eq!(
+            reject_rule("Row does not have the correct number of fields"),
+            "field_count"
+        );
+        assert_eq!(
+            reject_rule("invalid digit found in string"),
+            "invalid_number"
+        );
+        assert_eq!(
+            reject_rule("cannot parse integer from empty string"),
+            "invalid_number"
+        );
+        assert_eq!(
+            reject_rule("Parse error: Invalid district code \"CCA01\": only digits are allowed"),
+            "invalid_code"
+        );
+        assert_eq!(
+            reject_rule("Error connecting to DuckDB: Constraint Error"),
+            "insert"
+        );
+    }
+
+    #[test]
+    fn test_render_histogram_buckets_are_cumulative() {
+        let metrics = Metrics::new();
+        metrics.observe_http(Some(200), Duration::from_millis(200));
+        metrics.observe_http(Some(404), Duration::from_secs(3));
+        metrics.observe_http(None, Duration::from_secs(120));
+
+        let rendered = metrics.render();
+        assert!(rendered.contains("rust_hive_http_responses_total{status=\"200\"} 1"));
+        assert!(rendered.contains("rust_hive_http_responses_total{status=\"error\"} 1"));
+        assert!(rendered.contains("rust_hive_http_request_duration_seconds_bucket{le=\"0.25\"} 1"));
+        assert!(rendered.contains("rust_hive_http_request_duration_seconds_bucket{le=\"5\"} 2"));
+        assert!(rendered.contains("rust_hive_http_request_duration_seconds_bucket{le=\"+Inf\"} 3"));
+        assert!(rendered.contains("rust_hive_http_request_duration_seconds_count 3"));
+    }
+}
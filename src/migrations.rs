@@ -0,0 +1,174 @@
+use duckdb::{params, Connection, Result};
+use tracing::info;
+
+/// A step bringing the warehouse schema from `version - 1` to `version`.
+///
+/// Steps must be idempotent, so a warehouse created before `schema_version` existed can be
+/// migrated from the first step.
+#[derive(Debug, Clone, Copy)]
+pub struct Migration {
+    pub version: i64,
+    pub description: &'static str,
+    pub apply: fn(&Connection) -> Result<()>,
+}
+
+/// Whether a migration has already been applied to the warehouse.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: &'static str,
+    pub applied: bool,
+}
+
+/// Creates the `schema_version` table recording the applied migrations.
+pub fn ensure_schema_version_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version BIGINT PRIMARY KEY,
+            description TEXT,
+            applied_at TIMESTAMP DEFAULT current_timestamp
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Returns the highest applied version, `0` for a warehouse without any migration.
+pub fn current_version(conn: &Connection) -> Result<i64> {
+    ensure_schema_version_table(conn)?;
+    conn.query_row(
+        "SELECT coalesce(max(version), 0) FROM schema_version",
+        [],
+        |row| row.get(0),
+    )
+}
+
+/// Lists every migration with whether it has been applied, in order.
+pub fn migration_status(
+    conn: &Connection,
+    migrations: &[Migration],
+) -> Result<Vec<MigrationStatus>> {
+    let current = current_version(conn)?;
+    Ok(migrations
+        .iter()
+        .map(|migration| MigrationStatus {
+            version: migration.version,
+            description: migration.description,
+            applied: migration.version <= current,
+        })
+        .collect())
+}
+
+/// Lists the migrations which are not applied yet, in order.
+pub fn pending_migrations<'a>(
+    conn: &Connection,
+    migrations: &'a [Migration],
+) -> Result<Vec<&'a Migration>> {
+    let current = current_version(conn)?;
+    Ok(migrations
+        .iter()
+        .filter(|migration| migration.version > current)
+        .collect())
+}
+
+/// Applies the pending migrations in order, each in its own transaction with its version.
+///
+/// # Arguments
+///
+/// * `conn` - A reference to a DuckDB Connection to the warehouse.
+/// * `migrations` - Every migration of the warehouse, ordered by version.
+///
+/// # Returns
+///
+/// * `Result<Vec<i64>>` - The versions applied by this call.
+///
+pub fn migrate(conn: &Connection, migrations: &[Migration]) -> Result<Vec<i64>> {
+    debug_assert!(migrations.windows(2).all(|w| w[0].version < w[1].version));
+    let mut applied = vec![];
+    for migration in pending_migrations(conn, migrations)? {
+        conn.execute_batch("BEGIN TRANSACTION")?;
+        let result = (migration.apply)(conn).and_then(|_| {
+            conn.execute(
+                "INSERT INTO schema_version (version, description) VALUES (?, ?)",
+                params![migration.version, migration.description],
+            )
+        });
+        match result {
+            Ok(_) => conn.execute_batch("COMMIT")?,
+            Err(e) => {
+                conn.execute_batch("ROLLBACK")?;
+                return Err(e);
+            }
+        }
+        info!(
+            version = migration.version,
+            description = migration.description,
+            "Applied migration"
+        );
+        applied.push(migration.version);
+    }
+    Ok(applied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_items(conn: &Connection) -> Result<()> {
+        conn.execute("CREATE TABLE IF NOT EXISTS items (id INTEGER)", [])?;
+        Ok(())
+    }
+
+    fn add_name(conn: &Connection) -> Result<()> {
+        conn.execute("ALTER TABLE items ADD COLUMN IF NOT EXISTS name TEXT", [])?;
+        Ok(())
+    }
+
+    fn broken(conn: &Connection) -> Result<()> {
+        conn.execute("CREATE TABLE broken (id INTEGER)", [])?;
+        conn.execute("SELECT * FROM missing", [])?;
+        Ok(())
+    }
+
+    const MIGRATIONS: [Migration; 2] = [
+        Migration {
+            version: 1,
+            description: "Create items",
+            apply: create_items,
+        },
+        Migration {
+            version: 2,
+            description: "Add items.name",
+            apply: add_name,
+        },
+    ];
+
+    #[test]
+    fn test_migrate_applies_pending_steps_once() {
+        let conn = Connection::open_in_memory().unwrap();
+        assert_eq!(pending_migrations(&conn, &MIGRATIONS).unwrap().len(), 2);
+
+        assert_eq!(migrate(&conn, &MIGRATIONS[..1]).unwrap(), vec![1]);
+        let status = migration_status(&conn, &MIGRATIONS).unwrap();
+        assert!(status[0].applied && !status[1].applied);
+
+        assert_eq!(migrate(&conn, &MIGRATIONS).unwrap(), vec![2]);
+        assert!(migrate(&conn, &MIGRATIONS).unwrap().is_empty());
+        assert_eq!(current_version(&conn).unwrap(), 2);
+        conn.execute("INSERT INTO items (id, name) VALUES (1, 'a')", [])
+            .unwrap();
+    }
+
+    #[test]
+    fn test_failed_migration_is_rolled_back() {
+        let conn = Connection::open_in_memory().unwrap();
+        let migrations = [Migration {
+            version: 1,
+            description: "Broken",
+            apply: broken,
+        }];
+        assert!(migrate(&conn, &migrations).is_err());
+        assert_eq!(current_version(&conn).unwrap(), 0);
+        assert!(conn.execute("SELECT * FROM broken", []).is_err());
+    }
+}
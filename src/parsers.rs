@@ -1,6 +1,12 @@
 #![allow(dead_code)]
 
 pub mod population {
+    use crate::calendar::{CalendarError, YearMonth};
+    use crate::codes::{
+        CodeError, DistrictCode, ProvinceCode, RegistrationOfficeCode, SubdistrictCode,
+    };
+    use crate::schema::row_schema;
+
     pub fn clean_text(text: &str) -> String {
         text.trim_matches(|c| ['\u{feff}', '|'].contains(&c))
             .to_string()
@@ -29,24 +35,32 @@ pub mod population {
         }
     }
 
-    #[derive(Debug)]
-    pub struct PopulationRow {
-        pub yymm: String,
-        pub cc_code: i32,
-        pub cc_desc: String,
-        pub rcode_code: String,
-        pub rcode_desc: String,
-        pub ccaatt_code: String,
-        pub ccaatt_desc: String,
-        pub ccaattmm_code: String,
-        pub ccaattmm_desc: String,
-        pub male: i32,
-        pub female: i32,
-        pub total: i32,
-        pub house: i32,
+    row_schema! {
+        /// A line of a DOPA statistic file, without the year of the file it was read from.
+        #[derive(Debug, Clone, PartialEq)]
+        pub struct PopulationRow {
+            pub yymm: String => "TEXT",
+            pub cc_code: ProvinceCode => "INTEGER",
+            pub cc_desc: String => "TEXT",
+            pub rcode_code: RegistrationOfficeCode => "TEXT",
+            pub rcode_desc: String => "TEXT",
+            pub ccaatt_code: DistrictCode => "TEXT",
+            pub ccaatt_desc: String => "TEXT",
+            pub ccaattmm_code: SubdistrictCode => "TEXT",
+            pub ccaattmm_desc: String => "TEXT",
+            pub male: i32 => "INTEGER",
+            pub female: i32 => "INTEGER",
+            pub total: i32 => "INTEGER",
+            pub house: i32 => "INTEGER",
+        }
     }
 
     impl PopulationRow {
+        /// Reads the month of the row from its `yymm` field.
+        pub fn year_month(&self) -> Result<YearMonth, CalendarError> {
+            YearMonth::parse_yymm(&self.yymm)
+        }
+
         pub fn string_to_int(value: &str) -> Result<i32, std::num::ParseIntError> {
             value.replace(",", "").parse::<i32>()
         }
@@ -61,13 +75,13 @@ pub mod population {
 
             Ok(PopulationRow {
                 yymm: fields[0].to_string(),
-                cc_code: Self::string_to_int(&fields[1]).map_err(|e| e.to_string())?,
+                cc_code: fields[1].parse().map_err(|e: CodeError| e.to_string())?,
                 cc_desc: fields[2].to_string(),
-                rcode_code: fields[3].to_string(),
+                rcode_code: fields[3].parse().map_err(|e: CodeError| e.to_string())?,
                 rcode_desc: fields[4].to_string(),
-                ccaatt_code: fields[5].to_string(),
+                ccaatt_code: fields[5].parse().map_err(|e: CodeError| e.to_string())?,
                 ccaatt_desc: fields[6].to_string(),
-                ccaattmm_code: fields[7].to_string(),
+                ccaattmm_code: fields[7].parse().map_err(|e: CodeError| e.to_string())?,
                 ccaattmm_desc: fields[8].to_string(),
                 male: Self::string_to_int(&fields[9]).map_err(|e| e.to_string())?,
                 female: Self::string_to_int(&fields[10]).map_err(|e| e.to_string())?,
@@ -76,11 +90,23 @@ pub mod population {
             })
         }
     }
+
+    /// Parses the lines of a DOPA statistic file with their line number, from 1, skipping the
+    /// blank lines.
+    pub fn parse_lines(
+        text: &str,
+    ) -> impl Iterator<Item = (usize, Result<PopulationRow, String>)> + '_ {
+        text.lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line.trim_matches([' ', '\r'])))
+            .filter(|(_, line)| !line.is_empty())
+            .map(|(number, line)| (number, PopulationRow::parse(line.to_string())))
+    }
 }
 
 fn main() {
     // Cases: string
-    let row_str = "|2023|001|Description|RC01|Region Description|CCA01|CCAATT Desc|CCAMM01|CCAATTMM Desc|1234|5678|6912|345|";
+    let row_str = "|6612|001|Description|0101|Region Description|010100|CCAATT Desc|01010100|CCAATTMM Desc|1234|5678|6912|345|";
 
     match population::PopulationRow::parse(row_str.to_string()) {
         Ok(population_row) => {
@@ -96,14 +122,14 @@ fn main() {
 
     // Cases: vector of strings
     let row_vec = vec![
-        "2023",
+        "6612",
         "001",
         "Description",
-        "RC01",
+        "0101",
         "Region Description",
-        "CCA01",
+        "010100",
         "CCAATT Desc",
-        "CCAMM01",
+        "01010100",
         "CCAATTMM Desc",
         "1234",
         "5678",
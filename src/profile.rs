@@ -0,0 +1,477 @@
+use crate::anomalies::Anomaly;
+use crate::parsers::population::PopulationRow;
+use crate::progress::IngestionSummary;
+use crate::schema::RowSchema;
+use duckdb::{Connection, Result};
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+/// Where the profiling report of a run is written by default.
+pub const PROFILE_REPORT_PATH: &str = "./datasets/profile_report.md";
+
+/// The count columns whose distribution is profiled.
+const COUNT_COLUMNS: [&str; 4] = ["male", "female", "total", "house"];
+
+/// A titled table of the report: its header and its rows of cells.
+type Section = (&'static str, Vec<&'static str>, Vec<Vec<String>>);
+
+/// What `thai_population` holds for a year.
+#[derive(Debug, Clone, PartialEq)]
+pub struct YearProfile {
+    pub data_year: i32,
+    pub rows: i64,
+    /// Lines rejected by the run, `None` when the year was not loaded by it.
+    pub rejected: Option<u64>,
+    pub provinces: i64,
+    pub registration_offices: i64,
+    pub districts: i64,
+    pub subdistricts: i64,
+    /// Province codes missing from the previous year, empty for the first year.
+    pub new_codes: Vec<i32>,
+    /// Province codes of the previous year missing from this one.
+    pub disappeared_codes: Vec<i32>,
+}
+
+/// Share of the rows where a column is NULL or blank.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnProfile {
+    pub name: &'static str,
+    pub empty_rate: f64,
+}
+
+/// Summary statistics of a count column over the province lines.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Distribution {
+    pub column: &'static str,
+    pub min: i64,
+    pub p25: f64,
+    pub median: f64,
+    pub p75: f64,
+    pub max: i64,
+    pub mean: f64,
+}
+
+/// Profile of the warehouse after an ingestion run, rendered as Markdown or HTML.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProfileReport {
+    pub years: Vec<YearProfile>,
+    pub columns: Vec<ColumnProfile>,
+    pub distributions: Vec<Distribution>,
+    pub anomalies: Vec<Anomaly>,
+}
+
+impl ProfileReport {
+    /// Profiles `thai_population`, taking the rejected lines from the summary of the run and
+    /// the anomalies flagged after it.
+    pub fn build(
+        conn: &Connection,
+        summary: &IngestionSummary,
+        anomalies: &[Anomaly],
+    ) -> Result<Self> {
+        Ok(ProfileReport {
+            years: year_profiles(conn, summary)?,
+            columns: column_profiles(conn)?,
+            distributions: distributions(conn)?,
+            anomalies: anomalies.to_vec(),
+        })
+    }
+
+    fn sections(&self) -> Vec<Section> {
+        let codes = |codes: &[i32]| {
+            codes
+                .iter()
+                .map(|code| format!("{:02}", code))
+                .collect::<Vec<String>>()
+                .join(", ")
+        };
+        vec![
+            (
+                "Years",
+                vec![
+                    "Year",
+                    "Rows",
+                    "Rejected",
+                    "Provinces",
+                    "Registration offices",
+                    "Districts",
+                    "Subdistricts",
+                    "New codes",
+                    "Disappeared codes",
+                ],
+                self.years
+                    .iter()
+                    .map(|year| {
+                        vec![
+                            year.data_year.to_string(),
+                            year.rows.to_string(),
+                            year.rejected.map_or("-".to_string(), |r| r.to_string()),
+                            year.provinces.to_string(),
+                            year.registration_offices.to_string(),
+                            year.districts.to_string(),
+                            year.subdistricts.to_string(),
+                            codes(&year.new_codes),
+                            codes(&year.disappeared_codes),
+                        ]
+                    })
+                    .collect(),
+            ),
+            (
+                "Empty values",
+                vec!["Column", "NULL or blank"],
+                self.columns
+                    .iter()
+                    .map(|column| {
+                        vec![
+                            column.name.to_string(),
+                            format!("{:.2}%", column.empty_rate * 100.0),
+                        ]
+                    })
+                    .collect(),
+            ),
+            (
+                "Distributions (provinces)",
+                vec!["Column", "Min", "P25", "Median", "P75", "Max", "Mean"],
+                self.distributions
+                    .iter()
+                    .map(|d| {
+                        vec![
+                            d.column.to_string(),
+                            d.min.to_string(),
+                            format!("{:.0}", d.p25),
+                            format!("{:.0}", d.median),
+                            format!("{:.0}", d.p75),
+                            d.max.to_string(),
+                            format!("{:.1}", d.mean),
+                        ]
+                    })
+                    .collect(),
+            ),
+            (
+                "Anomalies",
+                vec!["Year", "Code", "Name", "Kind", "Value", "Score"],
+                self.anomalies
+                    .iter()
+                    .map(|anomaly| {
+                        vec![
+                            anomaly.data_year.to_string(),
+                            format!("{:02}", anomaly.cc_code),
+                            anomaly.cc_desc.trim().to_string(),
+                            anomaly.kind.clone(),
+                            format!("{:.4}", anomaly.value),
+                            format!("{:.2}", anomaly.score),
+                        ]
+                    })
+                    .collect(),
+            ),
+        ]
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut out = "# Profile of thai_population\n".to_string();
+        for (title, header, rows) in self.sections() {
+            let _ = write!(out, "\n## {}\n\n", title);
+            if rows.is_empty() {
+                out.push_str("None.\n");
+                continue;
+            }
+            let _ = writeln!(out, "| {} |", header.join(" | "));
+            let _ = writeln!(out, "|{}", " --- |".repeat(header.len()));
+            for row in rows {
+                let cells = row
+                    .iter()
+                    .map(|cell| cell.replace('|', "\\|"))
+                    .collect::<Vec<String>>();
+                let _ = writeln!(out, "| {} |", cells.join(" | "));
+            }
+        }
+        out
+    }
+
+    pub fn to_html(&self) -> String {
+        let escape = |text: &str| {
+            text.replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;")
+        };
+        let mut out = "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\">\
+            <title>Profile of thai_population</title></head>\n<body>\n\
+            <h1>Profile of thai_population</h1>\n"
+            .to_string();
+        for (title, header, rows) in self.sections() {
+            let _ = writeln!(out, "<h2>{}</h2>", escape(title));
+            if rows.is_empty() {
+                out.push_str("<p>None.</p>\n");
+                continue;
+            }
+            let _ = writeln!(
+                out,
+                "<table>\n<tr>{}</tr>",
+                header
+                    .iter()
+                    .map(|cell| format!("<th>{}</th>", escape(cell)))
+                    .collect::<String>()
+            );
+            for row in rows {
+                let _ = writeln!(
+                    out,
+                    "<tr>{}</tr>",
+                    row.iter()
+                        .map(|cell| format!("<td>{}</td>", escape(cell)))
+                        .collect::<String>()
+                );
+            }
+            out.push_str("</table>\n");
+        }
+        out.push_str("</body>\n</html>\n");
+        out
+    }
+
+    /// Writes the report as HTML when `path` ends with `.html`, as Markdown otherwise.
+    pub fn write(&self, path: &Path) -> std::io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let html = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("html"));
+        fs::write(
+            path,
+            if html {
+                self.to_html()
+            } else {
+                self.to_markdown()
+            },
+        )
+    }
+}
+
+fn year_profiles(conn: &Connection, summary: &IngestionSummary) -> Result<Vec<YearProfile>> {
+    let distinct = |column: &str| {
+        format!(
+            "count(DISTINCT {0}) FILTER (WHERE NOT regexp_matches({0}, '^0*$'))",
+            column
+        )
+    };
+    let mut stmt = conn.prepare(&format!(
+        "SELECT
+            data_year,
+            count(*),
+            count(DISTINCT cc_code) FILTER (WHERE cc_code <> 0),
+            {},
+            {},
+            {},
+            list(DISTINCT cc_code ORDER BY cc_code) FILTER (WHERE cc_code <> 0)::VARCHAR
+        FROM thai_population
+        GROUP BY data_year
+        ORDER BY data_year",
+        distinct("rcode_code"),
+        distinct("ccaatt_code"),
+        distinct("ccaattmm_code")
+    ))?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, i32>(0)?,
+                [
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                    row.get(5)?,
+                ],
+                row.get::<_, Option<String>>(6)?,
+            ))
+        })?
+        .collect::<Result<Vec<(i32, [i64; 5], Option<String>)>>>()?;
+
+    let mut previous: Option<BTreeSet<i32>> = None;
+    let mut years = vec![];
+    for (data_year, counts, codes) in rows {
+        let codes = codes
+            .unwrap_or_default()
+            .trim_matches(|c| c == '[' || c == ']')
+            .split(',')
+            .filter_map(|code| code.trim().parse().ok())
+            .collect::<BTreeSet<i32>>();
+        let (new_codes, disappeared_codes) = match &previous {
+            Some(previous) => (
+                codes.difference(previous).copied().collect(),
+                previous.difference(&codes).copied().collect(),
+            ),
+            None => (vec![], vec![]),
+        };
+        years.push(YearProfile {
+            data_year,
+            rows: counts[0],
+            rejected: summary
+                .years
+                .get(&data_year)
+                .filter(|year| year.rows_parsed + year.rows_rejected > 0)
+                .map(|year| year.rows_rejected),
+            provinces: counts[1],
+            registration_offices: counts[2],
+            districts: counts[3],
+            subdistricts: counts[4],
+            new_codes,
+            disappeared_codes,
+        });
+        previous = Some(codes);
+    }
+    Ok(years)
+}
+
+fn column_profiles(conn: &Connection) -> Result<Vec<ColumnProfile>> {
+    let rates = PopulationRow::COLUMNS
+        .iter()
+        .map(|column| {
+            format!(
+                "coalesce(avg(CASE WHEN {0} IS NULL OR trim(CAST({0} AS TEXT)) = '' \
+                THEN 1 ELSE 0 END), 0)",
+                column.name
+            )
+        })
+        .collect::<Vec<String>>()
+        .join(",\n");
+    conn.query_row(
+        &format!("SELECT {} FROM thai_population", rates),
+        [],
+        |row| {
+            PopulationRow::COLUMNS
+                .iter()
+                .enumerate()
+                .map(|(index, column)| {
+                    Ok(ColumnProfile {
+                        name: column.name,
+                        empty_rate: row.get(index)?,
+                    })
+                })
+                .collect()
+        },
+    )
+}
+
+fn distributions(conn: &Connection) -> Result<Vec<Distribution>> {
+    COUNT_COLUMNS
+        .into_iter()
+        .map(|column| {
+            conn.query_row(
+                &format!(
+                    "SELECT
+                        coalesce(min({0}), 0),
+                        coalesce(quantile_cont({0}, 0.25), 0),
+                        coalesce(median({0}), 0),
+                        coalesce(quantile_cont({0}, 0.75), 0),
+                        coalesce(max({0}), 0),
+                        coalesce(avg({0}), 0)
+                    FROM thai_population
+                    WHERE cc_code <> 0",
+                    column
+                ),
+                [],
+                |row| {
+                    Ok(Distribution {
+                        column,
+                        min: row.get(0)?,
+                        p25: row.get(1)?,
+                        median: row.get(2)?,
+                        p75: row.get(3)?,
+                        max: row.get(4)?,
+                        mean: row.get(5)?,
+                    })
+                },
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progress::{IngestionEvent, ProgressReporter};
+    use duckdb::params;
+
+    fn warehouse() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(
+            &format!(
+                "CREATE TABLE thai_population (data_year INTEGER, {})",
+                PopulationRow::column_definitions()
+            ),
+            [],
+        )
+        .unwrap();
+        for (year, cc_code, desc, total) in [
+            (2022, 0, "Country", 600),
+            (2022, 10, "Bangkok", 200),
+            (2022, 43, "Nong Khai", 400),
+            (2023, 10, "Bangkok", 220),
+            (2023, 38, "Bueng Kan", 180),
+            (2023, 43, "", 240),
+        ] {
+            conn.execute(
+                &format!(
+                    "INSERT INTO thai_population (data_year, {}) VALUES (?, {})",
+                    PopulationRow::column_list(),
+                    PopulationRow::placeholders()
+                ),
+                params![
+                    year,
+                    "6612",
+                    cc_code,
+                    desc,
+                    format!("{:02}01", cc_code),
+                    "",
+                    "0",
+                    "",
+                    "0",
+                    "",
+                    total / 2,
+                    total / 2,
+                    total,
+                    10
+                ],
+            )
+            .unwrap();
+        }
+        conn
+    }
+
+    #[test]
+    fn test_report_profiles_years_columns_and_distributions() {
+        let conn = warehouse();
+        let progress = ProgressReporter::new("test");
+        progress.report(IngestionEvent::RowParsed { data_year: 2023 });
+        progress.report(IngestionEvent::RowRejected {
+            data_year: 2023,
+            reason: "bad line".to_string(),
+        });
+        let report = ProfileReport::build(&conn, &progress.summary(), &[]).unwrap();
+
+        assert_eq!(report.years.len(), 2);
+        let (first, second) = (&report.years[0], &report.years[1]);
+        assert_eq!((first.rows, first.provinces, first.rejected), (3, 2, None));
+        assert_eq!(first.registration_offices, 3);
+        assert_eq!(second.rejected, Some(1));
+        assert_eq!(second.new_codes, vec![38]);
+        assert!(second.disappeared_codes.is_empty());
+        assert_eq!(second.districts, 0);
+
+        let cc_desc = report
+            .columns
+            .iter()
+            .find(|column| column.name == "cc_desc")
+            .unwrap();
+        assert!((cc_desc.empty_rate - 1.0 / 6.0).abs() < 1e-9);
+        let total = &report.distributions[2];
+        assert_eq!((total.column, total.min, total.max), ("total", 180, 400));
+        assert_eq!(total.median, 220.0);
+
+        let markdown = report.to_markdown();
+        assert!(markdown.contains("| 2023 | 3 | 1 | 3 | 3 | 0 | 0 | 38 |  |"));
+        assert!(markdown.contains("## Anomalies\n\nNone."));
+        let html = report.to_html();
+        assert!(html.contains("<tr><td>2022</td><td>3</td><td>-</td>"));
+    }
+}
@@ -0,0 +1,289 @@
+use indicatif::{ProgressBar, ProgressStyle};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Where the summary of the last run is written, next to the Hive partitioned dataset.
+pub const SUMMARY_PATH: &str = "./datasets/ingestion_summary.json";
+
+/// Something which happened during an ingestion run.
+///
+/// Every event carries the Gregorian `data_year` it belongs to, except `YearsDiscovered`
+/// which announces the whole set of years the run is going to process.
+#[derive(Debug, Clone, PartialEq)]
+pub enum IngestionEvent {
+    YearsDiscovered(Vec<i32>),
+    BytesFetched { data_year: i32, bytes: u64 },
+    RowParsed { data_year: i32 },
+    RowInserted { data_year: i32 },
+    RowRejected { data_year: i32, reason: String },
+    YearFinished { data_year: i32 },
+    YearSkipped { data_year: i32 },
+    YearFailed { data_year: i32, error: String },
+}
+
+/// Receives the events of an ingestion run as they happen.
+///
+/// Listeners are called from the worker threads, so they must be `Send + Sync`.
+pub trait ProgressListener: Send + Sync {
+    fn on_event(&self, event: &IngestionEvent);
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum YearStatus {
+    #[default]
+    Pending,
+    Finished,
+    Skipped,
+    Failed,
+}
+
+/// Counters collected for a single year.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct YearSummary {
+    pub status: YearStatus,
+    pub bytes_fetched: u64,
+    pub rows_parsed: u64,
+    pub rows_inserted: u64,
+    pub rows_rejected: u64,
+    pub error: Option<String>,
+}
+
+/// Machine-readable summary of a whole ingestion run.
+#[derive(Debug, Clone, Serialize)]
+pub struct IngestionSummary {
+    pub engine: String,
+    pub started_at_unix: u64,
+    pub elapsed_seconds: f64,
+    pub years_discovered: usize,
+    pub years_finished: usize,
+    pub years_skipped: usize,
+    pub years_failed: usize,
+    pub bytes_fetched: u64,
+    pub rows_parsed: u64,
+    pub rows_inserted: u64,
+    pub rows_rejected: u64,
+    pub years: BTreeMap<i32, YearSummary>,
+}
+
+impl IngestionSummary {
+    /// Writes the summary as pretty printed JSON, creating the parent directory if needed.
+    pub fn write_json(&self, path: &Path) -> std::io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(path, json)
+    }
+}
+
+/// Collects the events of a run into an `IngestionSummary` and forwards them to listeners.
+///
+/// The reporter is shared between threads behind an `Arc`.
+pub struct ProgressReporter {
+    engine: String,
+    started: Instant,
+    started_at: SystemTime,
+    years: Mutex<BTreeMap<i32, YearSummary>>,
+    listeners: Vec<Box<dyn ProgressListener>>,
+}
+
+impl ProgressReporter {
+    pub fn new(engine: &str) -> Self {
+        ProgressReporter {
+            engine: engine.to_string(),
+            started: Instant::now(),
+            started_at: SystemTime::now(),
+            years: Mutex::new(BTreeMap::new()),
+            listeners: vec![],
+        }
+    }
+
+    pub fn with_listener<L: ProgressListener + 'static>(mut self, listener: L) -> Self {
+        self.listeners.push(Box::new(listener));
+        self
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Records an event and forwards it to every listener.
+    pub fn report(&self, event: IngestionEvent) {
+        {
+            let mut years = self.years.lock().unwrap();
+            match &event {
+                IngestionEvent::YearsDiscovered(discovered) => {
+                    for year in discovered {
+                        years.entry(*year).or_default();
+                    }
+                }
+                IngestionEvent::BytesFetched { data_year, bytes } => {
+                    years.entry(*data_year).or_default().bytes_fetched += bytes;
+                }
+                IngestionEvent::RowParsed { data_year } => {
+                    years.entry(*data_year).or_default().rows_parsed += 1;
+                }
+                IngestionEvent::RowInserted { data_year } => {
+                    years.entry(*data_year).or_default().rows_inserted += 1;
+                }
+                IngestionEvent::RowRejected { data_year, .. } => {
+                    years.entry(*data_year).or_default().rows_rejected += 1;
+                }
+                IngestionEvent::YearFinished { data_year } => {
+                    years.entry(*data_year).or_default().status = YearStatus::Finished;
+                }
+                IngestionEvent::YearSkipped { data_year } => {
+                    years.entry(*data_year).or_default().status = YearStatus::Skipped;
+                }
+                IngestionEvent::YearFailed { data_year, error } => {
+                    let year = years.entry(*data_year).or_default();
+                    year.status = YearStatus::Failed;
+                    year.error = Some(error.clone());
+                }
+            }
+        }
+        for listener in &self.listeners {
+            listener.on_event(&event);
+        }
+    }
+
+    /// Builds the summary of everything reported so far.
+    pub fn summary(&self) -> IngestionSummary {
+        let years = self.years.lock().unwrap().clone();
+        let count = |status| years.values().filter(|y| y.status == status).count();
+        IngestionSummary {
+            engine: self.engine.clone(),
+            started_at_unix: self
+                .started_at
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            elapsed_seconds: self.elapsed().as_secs_f64(),
+            years_discovered: years.len(),
+            years_finished: count(YearStatus::Finished),
+            years_skipped: count(YearStatus::Skipped),
+            years_failed: count(YearStatus::Failed),
+            bytes_fetched: years.values().map(|y| y.bytes_fetched).sum(),
+            rows_parsed: years.values().map(|y| y.rows_parsed).sum(),
+            rows_inserted: years.values().map(|y| y.rows_inserted).sum(),
+            rows_rejected: years.values().map(|y| y.rows_rejected).sum(),
+            years,
+        }
+    }
+}
+
+/// Terminal progress bar advancing once per completed year.
+///
+/// The message shows the running totals of rows and bytes across all years.
+pub struct TerminalProgress {
+    bar: ProgressBar,
+    bytes: AtomicU64,
+    inserted: AtomicU64,
+    rejected: AtomicU64,
+}
+
+impl TerminalProgress {
+    pub fn new() -> Self {
+        let bar = ProgressBar::new(0);
+        bar.set_style(
+            ProgressStyle::with_template("[{elapsed_precise}] {bar:40} {pos}/{len} years {msg}")
+                .expect("Progress bar template is valid"),
+        );
+        TerminalProgress {
+            bar,
+            bytes: AtomicU64::new(0),
+            inserted: AtomicU64::new(0),
+            rejected: AtomicU64::new(0),
+        }
+    }
+
+    fn refresh_message(&self) {
+        self.bar.set_message(format!(
+            "{} rows inserted, {} rejected, {} KiB fetched",
+            self.inserted.load(Ordering::Relaxed),
+            self.rejected.load(Ordering::Relaxed),
+            self.bytes.load(Ordering::Relaxed) / 1024
+        ));
+    }
+}
+
+impl Default for TerminalProgress {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProgressListener for TerminalProgress {
+    fn on_event(&self, event: &IngestionEvent) {
+        match event {
+            IngestionEvent::YearsDiscovered(years) => self.bar.set_length(years.len() as u64),
+            IngestionEvent::BytesFetched { bytes, .. } => {
+                self.bytes.fetch_add(*bytes, Ordering::Relaxed);
+            }
+            IngestionEvent::RowParsed { .. } => return,
+            IngestionEvent::RowInserted { .. } => {
+                self.inserted.fetch_add(1, Ordering::Relaxed);
+            }
+            IngestionEvent::RowRejected { .. } => {
+                self.rejected.fetch_add(1, Ordering::Relaxed);
+            }
+            IngestionEvent::YearFinished { .. } | IngestionEvent::YearSkipped { .. } => {
+                self.bar.inc(1)
+            }
+            IngestionEvent::YearFailed { data_year, error } => {
+                self.bar
+                    .println(format!("Year {} failed: {}", data_year, error));
+                self.bar.inc(1);
+            }
+        }
+        self.refresh_message();
+        if !self.bar.is_finished() && self.bar.length() == Some(self.bar.position()) {
+            self.bar.finish();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_counts_events_per_year() {
+        let reporter = ProgressReporter::new("test");
+        reporter.report(IngestionEvent::YearsDiscovered(vec![2022, 2023]));
+        reporter.report(IngestionEvent::BytesFetched {
+            data_year: 2022,
+            bytes: 100,
+        });
+        reporter.report(IngestionEvent::RowParsed { data_year: 2022 });
+        reporter.report(IngestionEvent::RowInserted { data_year: 2022 });
+        reporter.report(IngestionEvent::RowRejected {
+            data_year: 2022,
+            reason: "Row does not have the correct number of fields".to_string(),
+        });
+        reporter.report(IngestionEvent::YearFinished { data_year: 2022 });
+        reporter.report(IngestionEvent::YearFailed {
+            data_year: 2023,
+            error: "Fail request with HTTP code: 404".to_string(),
+        });
+
+        let summary = reporter.summary();
+        assert_eq!(summary.years_discovered, 2);
+        assert_eq!(summary.years_finished, 1);
+        assert_eq!(summary.years_failed, 1);
+        assert_eq!(summary.bytes_fetched, 100);
+        assert_eq!(summary.rows_inserted, 1);
+        assert_eq!(summary.rows_rejected, 1);
+        assert_eq!(summary.years[&2022].rows_parsed, 1);
+        assert_eq!(summary.years[&2023].status, YearStatus::Failed);
+
+        let json = serde_json::to_value(&summary).unwrap();
+        assert_eq!(json["years"]["2023"]["status"], "failed");
+    }
+}
@@ -0,0 +1,383 @@
+use duckdb::{params, Connection, Result};
+use std::collections::BTreeMap;
+
+/// z-score of the two-sided 95% prediction intervals.
+const Z_95: f64 = 1.96;
+/// Years the growth model averages its rate over.
+const GROWTH_WINDOW: usize = 5;
+/// Smoothing parameters tried when fitting `Model::ExponentialSmoothing`.
+const SMOOTHING_GRID: [f64; 5] = [0.1, 0.3, 0.5, 0.7, 0.9];
+
+/// A model fitted to the yearly `total` of a `cc_code`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Model {
+    /// Least squares line over the years.
+    Linear,
+    /// Least squares line over the logarithm of the totals, i.e. a constant growth rate.
+    LogLinear,
+    /// Holt's linear exponential smoothing, its parameters picked on the one-step errors.
+    ExponentialSmoothing,
+    /// The average growth rate of the last years, carried forward.
+    Growth,
+}
+
+impl Model {
+    pub const ALL: [Model; 4] = [
+        Model::Linear,
+        Model::LogLinear,
+        Model::ExponentialSmoothing,
+        Model::Growth,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Model::Linear => "linear",
+            Model::LogLinear => "log_linear",
+            Model::ExponentialSmoothing => "exponential_smoothing",
+            Model::Growth => "growth",
+        }
+    }
+
+    /// Forecasts the `horizon` years following a series of consecutive years.
+    ///
+    /// Returns `None` for series shorter than three years, or holding a total of zero for the
+    /// models working on logarithms or rates.
+    pub fn forecast(&self, values: &[f64], horizon: usize) -> Option<Forecast> {
+        if values.len() < 3 {
+            return None;
+        }
+        let (fitted, points) = match self {
+            Model::Linear => linear_trend(values, horizon),
+            Model::LogLinear => {
+                if values.iter().any(|value| *value <= 0.0) {
+                    return None;
+                }
+                let logs = values.iter().map(|value| value.ln()).collect::<Vec<f64>>();
+                let (fitted, points) = linear_trend(&logs, horizon);
+                (
+                    fitted.into_iter().map(f64::exp).collect(),
+                    points.into_iter().map(f64::exp).collect(),
+                )
+            }
+            Model::ExponentialSmoothing => holt(values, horizon),
+            Model::Growth => {
+                let window = &values[values.len().saturating_sub(GROWTH_WINDOW + 1)..];
+                if window[0] <= 0.0 {
+                    return None;
+                }
+                let rate =
+                    (window[window.len() - 1] / window[0]).powf(1.0 / (window.len() - 1) as f64);
+                let last = values[values.len() - 1];
+                (
+                    values.iter().map(|value| value * rate).collect(),
+                    (1..=horizon).map(|h| last * rate.powi(h as i32)).collect(),
+                )
+            }
+        };
+        // `fitted[i]` predicts `values[i + 1]` for the one-step models, `values[i]` otherwise
+        let residuals = match self {
+            Model::Linear | Model::LogLinear => values
+                .iter()
+                .zip(&fitted)
+                .map(|(value, fit)| value - fit)
+                .collect::<Vec<f64>>(),
+            Model::ExponentialSmoothing | Model::Growth => values[1..]
+                .iter()
+                .zip(&fitted)
+                .map(|(value, fit)| value - fit)
+                .collect(),
+        };
+        let sigma = (residuals.iter().map(|r| r * r).sum::<f64>() / residuals.len() as f64).sqrt();
+        Some(Forecast { points, sigma })
+    }
+}
+
+/// Point forecasts of the years following a series, with the spread of the model's errors.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Forecast {
+    pub points: Vec<f64>,
+    /// Root mean square of the in-sample errors.
+    pub sigma: f64,
+}
+
+impl Forecast {
+    /// The approximate 95% interval of the forecast `h` years ahead, from 1: the in-sample
+    /// error spread widened with the square root of the horizon.
+    pub fn interval(&self, h: usize) -> (f64, f64) {
+        let point = self.points[h - 1];
+        let margin = Z_95 * self.sigma * (h as f64).sqrt();
+        ((point - margin).max(0.0), point + margin)
+    }
+}
+
+/// Fits a least squares line over the indexes of `values`, returning the fitted values and
+/// the `horizon` next points.
+fn linear_trend(values: &[f64], horizon: usize) -> (Vec<f64>, Vec<f64>) {
+    let n = values.len() as f64;
+    let mean_x = (n - 1.0) / 2.0;
+    let mean_y = values.iter().sum::<f64>() / n;
+    let (covariance, variance) = values.iter().enumerate().fold((0.0, 0.0), |acc, (x, y)| {
+        let dx = x as f64 - mean_x;
+        (acc.0 + dx * (y - mean_y), acc.1 + dx * dx)
+    });
+    let slope = covariance / variance;
+    let at = |x: f64| mean_y + slope * (x - mean_x);
+    (
+        (0..values.len()).map(|x| at(x as f64)).collect(),
+        (1..=horizon).map(|h| at(n - 1.0 + h as f64)).collect(),
+    )
+}
+
+/// Holt's linear exponential smoothing with the parameters of `SMOOTHING_GRID` giving the
+/// smallest one-step errors. Returns the one-step forecasts of `values[1..]` and the
+/// `horizon` next points.
+fn holt(values: &[f64], horizon: usize) -> (Vec<f64>, Vec<f64>) {
+    let run = |alpha: f64, beta: f64| {
+        let (mut level, mut trend) = (values[0], values[1] - values[0]);
+        let mut one_step = vec![];
+        for value in &values[1..] {
+            one_step.push(level + trend);
+            let previous = level;
+            level = alpha * value + (1.0 - alpha) * (level + trend);
+            trend = beta * (level - previous) + (1.0 - beta) * trend;
+        }
+        let sse = values[1..]
+            .iter()
+            .zip(&one_step)
+            .map(|(value, fit)| (value - fit).powi(2))
+            .sum::<f64>();
+        (sse, one_step, level, trend)
+    };
+    let (_, one_step, level, trend) = SMOOTHING_GRID
+        .iter()
+        .flat_map(|alpha| SMOOTHING_GRID.iter().map(move |beta| run(*alpha, *beta)))
+        .min_by(|a, b| a.0.total_cmp(&b.0))
+        .expect("the grid is not empty");
+    (
+        one_step,
+        (1..=horizon).map(|h| level + trend * h as f64).collect(),
+    )
+}
+
+/// Accuracy of a model on the years held out of its fit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Backtest {
+    pub cc_code: i32,
+    pub model: Model,
+    pub holdout_years: usize,
+    /// Mean absolute percentage error over the held out years, `0.05` for 5%.
+    pub mape: f64,
+}
+
+/// Reads the yearly totals of every `cc_code`, keeping the last run of consecutive years.
+fn load_series(conn: &Connection) -> Result<BTreeMap<i32, (i32, Vec<f64>)>> {
+    let mut stmt = conn.prepare(
+        "SELECT cc_code, data_year, CAST(total AS DOUBLE) FROM thai_population ORDER BY 1, 2",
+    )?;
+    let rows = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+        .collect::<Result<Vec<(i32, i32, f64)>>>()?;
+    let mut series: BTreeMap<i32, (i32, Vec<f64>)> = BTreeMap::new();
+    for (cc_code, year, total) in rows {
+        let (last_year, values) = series.entry(cc_code).or_insert((year - 1, vec![]));
+        if year != *last_year + 1 {
+            values.clear();
+        }
+        values.push(total);
+        *last_year = year;
+    }
+    Ok(series)
+}
+
+/// Rebuilds `thai_population_projections` with the forecasts of every model for the
+/// `horizon` years following the last year of each `cc_code`, with 95% intervals.
+///
+/// # Arguments
+///
+/// * `conn` - A reference to a DuckDB Connection to the warehouse.
+/// * `horizon` - The number of years to project.
+///
+/// # Returns
+///
+/// * `Result<usize>` - The number of projected rows.
+///
+pub fn build_projections(conn: &Connection, horizon: usize) -> Result<usize> {
+    let series = load_series(conn)?;
+    conn.execute_batch("BEGIN TRANSACTION")?;
+    match insert_projections(conn, &series, horizon) {
+        Ok(rows) => {
+            conn.execute_batch("COMMIT")?;
+            Ok(rows)
+        }
+        Err(e) => {
+            conn.execute_batch("ROLLBACK")?;
+            Err(e)
+        }
+    }
+}
+
+fn insert_projections(
+    conn: &Connection,
+    series: &BTreeMap<i32, (i32, Vec<f64>)>,
+    horizon: usize,
+) -> Result<usize> {
+    conn.execute(
+        "CREATE OR REPLACE TABLE thai_population_projections (
+            cc_code INTEGER,
+            model TEXT,
+            data_year INTEGER,
+            total DOUBLE,
+            lower DOUBLE,
+            upper DOUBLE
+        )",
+        [],
+    )?;
+    let mut insert =
+        conn.prepare("INSERT INTO thai_population_projections VALUES (?, ?, ?, ?, ?, ?)")?;
+    let mut rows = 0;
+    for (cc_code, (last_year, values)) in series {
+        for model in Model::ALL {
+            let Some(forecast) = model.forecast(values, horizon) else {
+                continue;
+            };
+            for h in 1..=horizon {
+                let (lower, upper) = forecast.interval(h);
+                insert.execute(params![
+                    cc_code,
+                    model.as_str(),
+                    last_year + h as i32,
+                    forecast.points[h - 1],
+                    lower,
+                    upper
+                ])?;
+                rows += 1;
+            }
+        }
+    }
+    Ok(rows)
+}
+
+/// Fits every model without the last `holdout` years of each `cc_code`, and rebuilds
+/// `thai_population_projection_backtests` with their error on those years.
+pub fn backtest(conn: &Connection, holdout: usize) -> Result<Vec<Backtest>> {
+    let mut backtests = vec![];
+    for (cc_code, (_, values)) in load_series(conn)? {
+        if holdout == 0 || values.len() < holdout + 3 {
+            continue;
+        }
+        let (train, actual) = values.split_at(values.len() - holdout);
+        for model in Model::ALL {
+            let Some(forecast) = model.forecast(train, holdout) else {
+                continue;
+            };
+            let mape = actual
+                .iter()
+                .zip(&forecast.points)
+                .map(|(actual, point)| ((point - actual) / actual).abs())
+                .sum::<f64>()
+                / holdout as f64;
+            backtests.push(Backtest {
+                cc_code,
+                model,
+                holdout_years: holdout,
+                mape,
+            });
+        }
+    }
+    conn.execute(
+        "CREATE OR REPLACE TABLE thai_population_projection_backtests (
+            cc_code INTEGER,
+            model TEXT,
+            holdout_years INTEGER,
+            mape DOUBLE
+        )",
+        [],
+    )?;
+    let mut insert =
+        conn.prepare("INSERT INTO thai_population_projection_backtests VALUES (?, ?, ?, ?)")?;
+    for backtest in &backtests {
+        insert.execute(params![
+            backtest.cc_code,
+            backtest.model.as_str(),
+            backtest.holdout_years as i32,
+            backtest.mape
+        ])?;
+    }
+    Ok(backtests)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_models_follow_their_trend() {
+        let linear = [100.0, 110.0, 120.0, 130.0];
+        let forecast = Model::Linear.forecast(&linear, 2).unwrap();
+        assert!((forecast.points[1] - 150.0).abs() < 1e-9);
+        assert!(forecast.sigma < 1e-9);
+
+        let geometric = [100.0, 110.0, 121.0, 133.1];
+        for model in [Model::LogLinear, Model::Growth] {
+            let forecast = model.forecast(&geometric, 1).unwrap();
+            assert!((forecast.points[0] - 146.41).abs() < 1e-6, "{:?}", model);
+        }
+        let forecast = Model::ExponentialSmoothing.forecast(&linear, 3).unwrap();
+        assert!((forecast.points[2] - 160.0).abs() < 1e-9);
+
+        let noisy = Model::Linear
+            .forecast(&[100.0, 130.0, 110.0, 140.0], 2)
+            .unwrap();
+        let (lower, upper) = noisy.interval(2);
+        assert!(lower < noisy.points[1] && noisy.points[1] < upper);
+        assert!(upper - lower > noisy.interval(1).1 - noisy.interval(1).0);
+        assert!(Model::Growth.forecast(&[1.0, 2.0], 1).is_none());
+    }
+
+    #[test]
+    fn test_projections_and_backtests_are_stored() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(
+            "CREATE TABLE thai_population (data_year INTEGER, cc_code INTEGER, total INTEGER)",
+            [],
+        )
+        .unwrap();
+        for (index, year) in (2015..=2023).enumerate() {
+            conn.execute(
+                "INSERT INTO thai_population VALUES (?, 10, ?), (?, 38, ?)",
+                params![year, 1000 + 10 * index as i32, year, 500],
+            )
+            .unwrap();
+        }
+        // A year missing before 2023 leaves a series too short to project
+        conn.execute(
+            "INSERT INTO thai_population VALUES (2020, 50, 10), (2022, 50, 10)",
+            [],
+        )
+        .unwrap();
+
+        assert_eq!(build_projections(&conn, 5).unwrap(), 2 * 4 * 5);
+        let (year, total): (i32, f64) = conn
+            .query_row(
+                "SELECT data_year, total FROM thai_population_projections
+                WHERE cc_code = 10 AND model = 'linear' ORDER BY data_year DESC LIMIT 1",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(year, 2028);
+        assert!((total - 1130.0).abs() < 1e-6);
+
+        let backtests = backtest(&conn, 3).unwrap();
+        assert_eq!(backtests.len(), 8);
+        assert!(backtests.iter().all(|backtest| backtest.mape < 0.01));
+        let stored: i64 = conn
+            .query_row(
+                "SELECT count(*) FROM thai_population_projection_backtests",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(stored, 8);
+    }
+}
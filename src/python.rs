@@ -0,0 +1,277 @@
+use crate::calendar::{to_short_buddhist_year, validate_data_year, CalendarError};
+use crate::parsers::population::{parse_lines, PopulationRow};
+use crate::schema::RowSchema;
+use duckdb::{params_from_iter, Connection, ToSql};
+use pyo3::exceptions::{PyConnectionError, PyIOError, PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use std::fs;
+use std::path::Path;
+use thiserror::Error;
+
+/// Where `ingest` and `export` find the warehouse unless told otherwise, as the binaries do.
+const WAREHOUSE_PATH: &str = "./datasets/thai_population.duckdb";
+
+/// Where `export` writes the Hive partitions unless told otherwise.
+const HIVE_DATASET_PATH: &str = "./datasets/thai_population";
+
+#[derive(Error, Debug)]
+enum BindingError {
+    #[error("Error connecting to DuckDB: {0}")]
+    DuckDB(#[from] duckdb::Error),
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Fetch error: {0}")]
+    Fetch(String),
+    #[error("Parse error on line {line}: {message}")]
+    Parse { line: usize, message: String },
+    #[error("{0}")]
+    Year(#[from] CalendarError),
+}
+
+impl From<BindingError> for PyErr {
+    fn from(error: BindingError) -> PyErr {
+        let message = error.to_string();
+        match error {
+            BindingError::DuckDB(_) => PyRuntimeError::new_err(message),
+            BindingError::Io(_) => PyIOError::new_err(message),
+            BindingError::Fetch(_) => PyConnectionError::new_err(message),
+            BindingError::Parse { .. } | BindingError::Year(_) => PyValueError::new_err(message),
+        }
+    }
+}
+
+/// A line of a DOPA statistic file, read-only and comparable like a frozen dataclass.
+#[pyclass(name = "PopulationRow", module = "rust_hive", frozen, eq)]
+#[derive(Clone, PartialEq)]
+struct PyPopulationRow(PopulationRow);
+
+#[pymethods]
+impl PyPopulationRow {
+    #[getter]
+    fn yymm(&self) -> &str {
+        &self.0.yymm
+    }
+
+    #[getter]
+    fn cc_code(&self) -> i32 {
+        self.0.cc_code.value()
+    }
+
+    #[getter]
+    fn cc_desc(&self) -> &str {
+        &self.0.cc_desc
+    }
+
+    #[getter]
+    fn rcode_code(&self) -> &str {
+        self.0.rcode_code.as_str()
+    }
+
+    #[getter]
+    fn rcode_desc(&self) -> &str {
+        &self.0.rcode_desc
+    }
+
+    #[getter]
+    fn ccaatt_code(&self) -> &str {
+        self.0.ccaatt_code.as_str()
+    }
+
+    #[getter]
+    fn ccaatt_desc(&self) -> &str {
+        &self.0.ccaatt_desc
+    }
+
+    #[getter]
+    fn ccaattmm_code(&self) -> &str {
+        self.0.ccaattmm_code.as_str()
+    }
+
+    #[getter]
+    fn ccaattmm_desc(&self) -> &str {
+        &self.0.ccaattmm_desc
+    }
+
+    #[getter]
+    fn male(&self) -> i32 {
+        self.0.male
+    }
+
+    #[getter]
+    fn female(&self) -> i32 {
+        self.0.female
+    }
+
+    #[getter]
+    fn total(&self) -> i32 {
+        self.0.total
+    }
+
+    #[getter]
+    fn house(&self) -> i32 {
+        self.0.house
+    }
+
+    /// The fields by column name, in the order of the `thai_population` columns.
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new(py);
+        dict.set_item("yymm", self.yymm())?;
+        dict.set_item("cc_code", self.cc_code())?;
+        dict.set_item("cc_desc", self.cc_desc())?;
+        dict.set_item("rcode_code", self.rcode_code())?;
+        dict.set_item("rcode_desc", self.rcode_desc())?;
+        dict.set_item("ccaatt_code", self.ccaatt_code())?;
+        dict.set_item("ccaatt_desc", self.ccaatt_desc())?;
+        dict.set_item("ccaattmm_code", self.ccaattmm_code())?;
+        dict.set_item("ccaattmm_desc", self.ccaattmm_desc())?;
+        dict.set_item("male", self.male())?;
+        dict.set_item("female", self.female())?;
+        dict.set_item("total", self.total())?;
+        dict.set_item("house", self.house())?;
+        Ok(dict)
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "PopulationRow(yymm='{}', cc_code={}, cc_desc='{}', male={}, female={}, total={}, \
+            house={})",
+            self.0.yymm,
+            self.0.cc_code.value(),
+            self.0.cc_desc,
+            self.0.male,
+            self.0.female,
+            self.0.total,
+            self.0.house
+        )
+    }
+}
+
+fn fetch_year(data_year: i32) -> Result<String, BindingError> {
+    let thai_year = to_short_buddhist_year(validate_data_year(data_year)?);
+    let url = format!(
+        "https://stat.bora.dopa.go.th/new_stat/file/{}/stat_c{}.txt",
+        thai_year, thai_year
+    );
+    let response = reqwest::blocking::get(url).map_err(|e| BindingError::Fetch(e.to_string()))?;
+    if !response.status().is_success() {
+        return Err(BindingError::Fetch(format!(
+            "Fail request with HTTP code: {:?}",
+            response.status().as_u16()
+        )));
+    }
+    let text = response
+        .text()
+        .map_err(|e| BindingError::Fetch(e.to_string()))?;
+    Ok(text.trim_matches(|c| c == ' ' || c == '\n').to_string())
+}
+
+fn parse_text(text: &str) -> Result<Vec<PopulationRow>, BindingError> {
+    parse_lines(text)
+        .map(|(line, row)| row.map_err(|message| BindingError::Parse { line, message }))
+        .collect()
+}
+
+fn open_warehouse(database: &str) -> Result<Connection, BindingError> {
+    if let Some(parent) = Path::new(database).parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let conn = Connection::open(database)?;
+    conn.execute(
+        &format!(
+            "CREATE TABLE IF NOT EXISTS thai_population (
+                data_year INTEGER,
+                {},
+                PRIMARY KEY (data_year, cc_code)
+            )",
+            PopulationRow::column_definitions()
+        ),
+        [],
+    )?;
+    Ok(conn)
+}
+
+fn ingest_years(years: &[i32], database: &str) -> Result<usize, BindingError> {
+    let mut conn = open_warehouse(database)?;
+    let mut inserted = 0;
+    for &data_year in years {
+        let rows = parse_text(&fetch_year(data_year)?)?;
+        let tx = conn.transaction()?;
+        tx.execute(
+            "DELETE FROM thai_population WHERE data_year = ?",
+            [data_year],
+        )?;
+        {
+            let mut stmt = tx.prepare(&format!(
+                "INSERT INTO thai_population (data_year, {}) VALUES (?, {})",
+                PopulationRow::column_list(),
+                PopulationRow::placeholders()
+            ))?;
+            for row in &rows {
+                let mut params: Vec<&dyn ToSql> = vec![&data_year];
+                params.extend(row.to_params());
+                inserted += stmt.execute(params_from_iter(params))?;
+            }
+        }
+        tx.commit()?;
+    }
+    Ok(inserted)
+}
+
+fn export_warehouse(database: &str, destination: &str) -> Result<(), BindingError> {
+    let conn = open_warehouse(database)?;
+    conn.execute(
+        &format!(
+            "COPY (SELECT data_year, {} FROM thai_population) TO '{}' (
+                FORMAT PARQUET,
+                PARTITION_BY (data_year),
+                OVERWRITE_OR_IGNORE,
+                COMPRESSION GZIP,
+                FILE_EXTENSION 'parquet.gz'
+            )",
+            PopulationRow::column_list(),
+            destination.replace('\'', "''")
+        ),
+        [],
+    )?;
+    Ok(())
+}
+
+/// Downloads the DOPA statistic file of a Gregorian year and returns its text.
+#[pyfunction]
+fn fetch(py: Python<'_>, data_year: i32) -> PyResult<String> {
+    Ok(py.allow_threads(|| fetch_year(data_year))?)
+}
+
+/// Parses the text of a DOPA statistic file into its rows, skipping blank lines.
+#[pyfunction]
+fn parse(text: &str) -> PyResult<Vec<PyPopulationRow>> {
+    Ok(parse_text(text)?.into_iter().map(PyPopulationRow).collect())
+}
+
+/// Fetches, parses and loads years into `thai_population`, replacing their previous rows, and
+/// returns the number of rows inserted.
+#[pyfunction]
+#[pyo3(signature = (years, database = WAREHOUSE_PATH))]
+fn ingest(py: Python<'_>, years: Vec<i32>, database: &str) -> PyResult<usize> {
+    Ok(py.allow_threads(|| ingest_years(&years, database))?)
+}
+
+/// Writes `thai_population` into Hive partitions, as the ingestion binaries do.
+#[pyfunction]
+#[pyo3(signature = (destination = HIVE_DATASET_PATH, database = WAREHOUSE_PATH))]
+fn export(py: Python<'_>, destination: &str, database: &str) -> PyResult<()> {
+    Ok(py.allow_threads(|| export_warehouse(database, destination))?)
+}
+
+/// The native part of the `rust_hive` Python package.
+#[pymodule]
+#[pyo3(name = "_rust_hive")]
+fn rust_hive_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyPopulationRow>()?;
+    m.add_function(wrap_pyfunction!(fetch, m)?)?;
+    m.add_function(wrap_pyfunction!(parse, m)?)?;
+    m.add_function(wrap_pyfunction!(ingest, m)?)?;
+    m.add_function(wrap_pyfunction!(export, m)?)?;
+    Ok(())
+}
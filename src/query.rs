@@ -0,0 +1,373 @@
+use crate::codes::ProvinceCode;
+use crate::geography::Region;
+use crate::parsers::population::PopulationRow;
+use crate::schema::RowSchema;
+use duckdb::{params, params_from_iter, Connection, Result, Row, Statement};
+use serde::Serialize;
+use std::ops::RangeInclusive;
+
+/// A row of `thai_population` with the year of the file it was loaded from.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PopulationRecord {
+    pub data_year: i32,
+    #[serde(flatten)]
+    pub row: PopulationRow,
+}
+
+impl PopulationRecord {
+    pub(crate) fn from_row(row: &Row) -> Result<Self> {
+        Ok(PopulationRecord {
+            data_year: row.get(0)?,
+            row: PopulationRow::from_row(row, 1)?,
+        })
+    }
+}
+
+/// Level the counts are summed up to by `PopulationQuery::aggregate`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggregateLevel {
+    Country,
+    Region,
+    Province,
+}
+
+/// Counts of a year summed up to an `AggregateLevel`.
+///
+/// `key` is empty for the country, the region name or the zero-padded province code.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AggregateRow {
+    pub data_year: i32,
+    pub key: String,
+    pub male: i64,
+    pub female: i64,
+    pub total: i64,
+    pub house: i64,
+}
+
+/// Change of the total population of a province between two years.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProvinceGrowth {
+    pub cc_code: ProvinceCode,
+    pub cc_desc: String,
+    pub from_total: i64,
+    pub to_total: i64,
+    /// Relative change, e.g. `0.05` for +5%.
+    pub growth: f64,
+}
+
+/// A province of the warehouse, with its latest name and the years it appears in.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProvinceSummary {
+    pub cc_code: ProvinceCode,
+    pub cc_desc: String,
+    pub region: Option<&'static str>,
+    pub first_year: i32,
+    pub last_year: i32,
+}
+
+/// Typed filters over the `thai_population` table.
+///
+/// Filters combine with AND. The line of the national total (`cc_code` 0) is left out unless
+/// it is asked for by `province`, so sums over provinces are not counted twice.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PopulationQuery {
+    years: Option<RangeInclusive<i32>>,
+    provinces: Vec<ProvinceCode>,
+    region: Option<Region>,
+}
+
+impl PopulationQuery {
+    pub fn new() -> Self {
+        PopulationQuery::default()
+    }
+
+    /// Keeps the Gregorian years of `years`, both ends included.
+    pub fn years(mut self, years: RangeInclusive<i32>) -> Self {
+        self.years = Some(years);
+        self
+    }
+
+    /// Keeps the given province; may be called several times.
+    pub fn province(mut self, province: ProvinceCode) -> Self {
+        self.provinces.push(province);
+        self
+    }
+
+    /// Keeps the provinces of a region.
+    pub fn region(mut self, region: Region) -> Self {
+        self.region = Some(region);
+        self
+    }
+
+    /// The WHERE clause of the filters with its parameters.
+    fn where_clause(&self) -> (String, Vec<i32>) {
+        let mut conditions = vec![];
+        let mut params = vec![];
+        if let Some(years) = &self.years {
+            conditions.push("data_year BETWEEN ? AND ?".to_string());
+            params.extend([*years.start(), *years.end()]);
+        }
+        if self.provinces.is_empty() {
+            conditions.push("cc_code <> 0".to_string());
+        } else {
+            conditions.push(format!(
+                "cc_code IN ({})",
+                vec!["?"; self.provinces.len()].join(", ")
+            ));
+            params.extend(self.provinces.iter().map(|code| code.value()));
+        }
+        if let Some(region) = &self.region {
+            conditions.push(format!(
+                "cc_code // 10 IN ({})",
+                vec!["?"; region.code_prefixes().len()].join(", ")
+            ));
+            params.extend(region.code_prefixes());
+        }
+        (format!("WHERE {}", conditions.join(" AND ")), params)
+    }
+
+    /// Prepares the statement selecting the matching rows, to be read with `rows`.
+    pub fn prepare<'c>(&self, conn: &'c Connection) -> Result<Statement<'c>> {
+        let (where_clause, _) = self.where_clause();
+        conn.prepare(&format!(
+            "SELECT data_year, {} FROM thai_population {} ORDER BY data_year, cc_code",
+            PopulationRow::column_list(),
+            where_clause
+        ))
+    }
+
+    /// Streams the matching rows out of a statement from `prepare`, one at a time.
+    ///
+    /// ```ignore
+    /// let query = PopulationQuery::new().years(2020..=2023);
+    /// let mut stmt = query.prepare(&conn)?;
+    /// for record in query.rows(&mut stmt)? {
+    ///     println!("{:?}", record?);
+    /// }
+    /// ```
+    pub fn rows<'s>(
+        &self,
+        stmt: &'s mut Statement<'_>,
+    ) -> Result<impl Iterator<Item = Result<PopulationRecord>> + 's> {
+        let (_, params) = self.where_clause();
+        stmt.query_map(params_from_iter(params), PopulationRecord::from_row)
+    }
+
+    /// Collects the matching rows, ordered by year and province.
+    pub fn fetch(&self, conn: &Connection) -> Result<Vec<PopulationRecord>> {
+        let mut stmt = self.prepare(conn)?;
+        let records = self.rows(&mut stmt)?.collect();
+        records
+    }
+
+    /// Sums the counts of the matching rows per year and per `level`.
+    pub fn aggregate(
+        &self,
+        conn: &Connection,
+        level: AggregateLevel,
+    ) -> Result<Vec<AggregateRow>> {
+        let key = match level {
+            AggregateLevel::Country => "''".to_string(),
+            AggregateLevel::Region => Region::sql_case("cc_code"),
+            AggregateLevel::Province => "lpad(CAST(cc_code AS TEXT), 2, '0')".to_string(),
+        };
+        let (where_clause, params) = self.where_clause();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT
+                data_year,
+                {} AS key,
+                CAST(sum(male) AS BIGINT),
+                CAST(sum(female) AS BIGINT),
+                CAST(sum(total) AS BIGINT),
+                CAST(sum(house) AS BIGINT)
+            FROM thai_population {}
+            GROUP BY ALL
+            ORDER BY data_year, key",
+            key, where_clause
+        ))?;
+        let rows = stmt
+            .query_map(params_from_iter(params), |row| {
+                Ok(AggregateRow {
+                    data_year: row.get(0)?,
+                    key: row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+                    male: row.get(2)?,
+                    female: row.get(3)?,
+                    total: row.get(4)?,
+                    house: row.get(5)?,
+                })
+            })?
+            .collect();
+        rows
+    }
+
+    /// Ranks the matching provinces by the growth of their total between two years.
+    ///
+    /// The year range filter is ignored, `from_year` and `to_year` are used instead.
+    pub fn top_growth(
+        &self,
+        conn: &Connection,
+        from_year: i32,
+        to_year: i32,
+        limit: usize,
+    ) -> Result<Vec<ProvinceGrowth>> {
+        let query = self
+            .clone()
+            .years(from_year.min(to_year)..=from_year.max(to_year));
+        let (where_clause, mut params) = query.where_clause();
+        params.extend([from_year, to_year]);
+        let mut stmt = conn.prepare(&format!(
+            "WITH selected AS (SELECT * FROM thai_population {})
+            SELECT
+                f.cc_code,
+                t.cc_desc,
+                CAST(f.total AS BIGINT),
+                CAST(t.total AS BIGINT),
+                (t.total - f.total) / f.total AS growth
+            FROM selected f
+            JOIN selected t ON t.cc_code = f.cc_code
+            WHERE f.data_year = ? AND t.data_year = ? AND f.total > 0
+            ORDER BY growth DESC, f.cc_code
+            LIMIT {}",
+            where_clause, limit
+        ))?;
+        let rows = stmt
+            .query_map(params_from_iter(params), |row| {
+                Ok(ProvinceGrowth {
+                    cc_code: row.get(0)?,
+                    cc_desc: row.get(1)?,
+                    from_total: row.get(2)?,
+                    to_total: row.get(3)?,
+                    growth: row.get(4)?,
+                })
+            })?
+            .collect();
+        rows
+    }
+}
+
+/// Lists the years stored in the warehouse, oldest first.
+pub fn loaded_years(conn: &Connection) -> Result<Vec<i32>> {
+    let mut stmt = conn.prepare("SELECT DISTINCT data_year FROM thai_population ORDER BY 1")?;
+    let years = stmt.query_map(params![], |row| row.get(0))?.collect();
+    years
+}
+
+/// Lists the provinces of the warehouse, without the national total, ordered by code.
+pub fn provinces(conn: &Connection) -> Result<Vec<ProvinceSummary>> {
+    let mut stmt = conn.prepare(
+        "SELECT cc_code, arg_max(cc_desc, data_year), min(data_year), max(data_year)
+        FROM thai_population
+        WHERE cc_code <> 0
+        GROUP BY cc_code
+        ORDER BY cc_code",
+    )?;
+    let provinces = stmt
+        .query_map([], |row| {
+            let cc_code: ProvinceCode = row.get(0)?;
+            Ok(ProvinceSummary {
+                cc_code,
+                cc_desc: row.get(1)?,
+                region: Region::of(cc_code).map(|region| region.as_str()),
+                first_year: row.get(2)?,
+                last_year: row.get(3)?,
+            })
+        })?
+        .collect();
+    provinces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn warehouse() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(
+            &format!(
+                "CREATE TABLE thai_population (data_year INTEGER, {})",
+                PopulationRow::column_definitions()
+            ),
+            [],
+        )
+        .unwrap();
+        for (year, cc_code, total) in [
+            (2022, 0, 300),
+            (2022, 10, 100),
+            (2022, 38, 200),
+            (2023, 0, 330),
+            (2023, 10, 120),
+            (2023, 38, 210),
+        ] {
+            conn.execute(
+                &format!(
+                    "INSERT INTO thai_population (data_year, {}) VALUES (?, {})",
+                    PopulationRow::column_list(),
+                    PopulationRow::placeholders()
+                ),
+                params![
+                    year,
+                    "6612",
+                    cc_code,
+                    "p",
+                    "0",
+                    "",
+                    "0",
+                    "",
+                    "0",
+                    "",
+                    total / 2,
+                    total / 2,
+                    total,
+                    1
+                ],
+            )
+            .unwrap();
+        }
+        conn
+    }
+
+    #[test]
+    fn test_fetch_filters_years_and_regions() {
+        let conn = warehouse();
+        let records = PopulationQuery::new()
+            .years(2023..=2023)
+            .region(Region::Northeast)
+            .fetch(&conn)
+            .unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].row.cc_code.value(), 38);
+        assert_eq!(records[0].row.total, 210);
+
+        let country = PopulationQuery::new()
+            .province(ProvinceCode::new(0).unwrap())
+            .fetch(&conn)
+            .unwrap();
+        assert_eq!(country.len(), 2);
+        assert_eq!(loaded_years(&conn).unwrap(), vec![2022, 2023]);
+    }
+
+    #[test]
+    fn test_aggregate_and_top_growth() {
+        let conn = warehouse();
+        let totals = PopulationQuery::new()
+            .aggregate(&conn, AggregateLevel::Country)
+            .unwrap();
+        assert_eq!(
+            totals.iter().map(|row| row.total).collect::<Vec<i64>>(),
+            vec![300, 330]
+        );
+        let regions = PopulationQuery::new()
+            .years(2022..=2022)
+            .aggregate(&conn, AggregateLevel::Region)
+            .unwrap();
+        assert_eq!(regions[0].key, "central");
+        assert_eq!(regions[1].key, "northeast");
+
+        let growth = PopulationQuery::new()
+            .top_growth(&conn, 2022, 2023, 1)
+            .unwrap();
+        assert_eq!(growth.len(), 1);
+        assert_eq!(growth[0].cc_code.value(), 10);
+        assert!((growth[0].growth - 0.2).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,296 @@
+use duckdb::{Connection, Result};
+use serde::Serialize;
+
+/// Lines of `thai_population` with their level, code and the code of their parent line.
+///
+/// A line is at the deepest level whose code is not a placeholder: sub-district, district,
+/// province, or the nation for `cc_code` 0.
+const LINES: &str = "
+    lines AS (
+        SELECT
+            *,
+            CASE
+                WHEN NOT regexp_matches(coalesce(trim(ccaattmm_code), ''), '^0*$')
+                    THEN 'subdistrict'
+                WHEN NOT regexp_matches(coalesce(trim(ccaatt_code), ''), '^0*$') THEN 'district'
+                WHEN cc_code <> 0 THEN 'province'
+                ELSE 'nation'
+            END AS level,
+            CASE level
+                WHEN 'subdistrict' THEN trim(ccaattmm_code)
+                WHEN 'district' THEN trim(ccaatt_code)
+                ELSE CAST(cc_code AS TEXT)
+            END AS area_code,
+            CASE level
+                WHEN 'subdistrict' THEN left(trim(ccaattmm_code), 6)
+                WHEN 'district' THEN CAST(cc_code AS TEXT)
+                WHEN 'province' THEN '0'
+            END AS parent_code
+        FROM thai_population
+    ),
+    children AS (
+        SELECT
+            data_year,
+            parent_code,
+            count(*) AS children,
+            sum(male) AS male,
+            sum(female) AS female,
+            sum(total) AS total,
+            sum(house) AS house
+        FROM lines
+        WHERE parent_code IS NOT NULL
+        GROUP BY data_year, parent_code
+    )";
+
+/// A count of an aggregate line which differs from the sum of its child lines.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Mismatch {
+    pub data_year: i32,
+    /// `nation`, `province` or `district`.
+    pub level: String,
+    pub area_code: String,
+    /// `male`, `female`, `total` or `house`.
+    pub column: String,
+    /// The count published on the aggregate line.
+    pub published: i64,
+    /// The sum of the count over the child lines.
+    pub summed: i64,
+    pub children: i64,
+}
+
+/// Rebuilds `thai_population_reconciliation` from `thai_population`, comparing the counts of
+/// every aggregate line with the sum of its child lines.
+///
+/// Sub-districts add up to their district, the first 6 digits of `ccaattmm_code`, districts to
+/// their province and provinces to the `cc_code` 0 line of the nation. Lines without children
+/// in the same year are not compared.
+///
+/// # Arguments
+///
+/// * `conn` - A reference to a DuckDB Connection to the warehouse.
+///
+/// # Returns
+///
+/// * `Result<Vec<Mismatch>>` - The counts which do not add up, ordered by year and area.
+///
+pub fn reconcile(conn: &Connection) -> Result<Vec<Mismatch>> {
+    conn.execute(
+        &format!(
+            "CREATE OR REPLACE TABLE thai_population_reconciliation AS
+            WITH {},
+            compared AS (
+                SELECT
+                    p.data_year,
+                    p.level,
+                    p.area_code,
+                    c.children,
+                    [p.male, p.female, p.total, p.house] AS published,
+                    [c.male, c.female, c.total, c.house] AS summed
+                FROM lines p
+                JOIN children c
+                    ON c.data_year = p.data_year AND c.parent_code = p.area_code
+            )
+            SELECT
+                data_year,
+                level,
+                area_code,
+                ['male', 'female', 'total', 'house'][i] AS column_name,
+                CAST(published[i] AS BIGINT) AS published,
+                CAST(summed[i] AS BIGINT) AS summed,
+                CAST(children AS BIGINT) AS children
+            FROM compared, range(1, 5) AS counts(i)
+            WHERE published[i] IS DISTINCT FROM summed[i]
+            ORDER BY data_year, area_code, i",
+            LINES
+        ),
+        [],
+    )?;
+    let mut stmt = conn.prepare(
+        "SELECT data_year, level, area_code, column_name, published, summed, children
+        FROM thai_population_reconciliation",
+    )?;
+    let mismatches = stmt
+        .query_map([], |row| {
+            Ok(Mismatch {
+                data_year: row.get(0)?,
+                level: row.get(1)?,
+                area_code: row.get(2)?,
+                column: row.get(3)?,
+                published: row.get(4)?,
+                summed: row.get(5)?,
+                children: row.get(6)?,
+            })
+        })?
+        .collect();
+    mismatches
+}
+
+/// Moves the aggregate lines of `thai_population`, those with child lines in the same year,
+/// into `thai_population_aggregates`, so sums over `thai_population` count every person once.
+///
+/// The aggregate lines already kept for the years moved are replaced.
+///
+/// # Arguments
+///
+/// * `conn` - A reference to a DuckDB Connection to the warehouse.
+///
+/// # Returns
+///
+/// * `Result<usize>` - The number of lines moved.
+///
+pub fn separate_aggregates(conn: &Connection) -> Result<usize> {
+    conn.execute_batch(&format!(
+        "CREATE TABLE IF NOT EXISTS thai_population_aggregates AS
+        FROM thai_population LIMIT 0;
+
+        CREATE OR REPLACE TEMP TABLE aggregate_lines AS
+        WITH {}
+        SELECT p.* EXCLUDE (level, area_code, parent_code)
+        FROM lines p
+        SEMI JOIN children c ON c.data_year = p.data_year AND c.parent_code = p.area_code;",
+        LINES
+    ))?;
+    conn.execute_batch("BEGIN TRANSACTION")?;
+    match move_aggregates(conn) {
+        Ok(moved) => {
+            conn.execute_batch("COMMIT; DROP TABLE aggregate_lines")?;
+            Ok(moved)
+        }
+        Err(e) => {
+            conn.execute_batch("ROLLBACK")?;
+            Err(e)
+        }
+    }
+}
+
+fn move_aggregates(conn: &Connection) -> Result<usize> {
+    conn.execute_batch(
+        "DELETE FROM thai_population_aggregates
+        WHERE data_year IN (SELECT data_year FROM aggregate_lines);
+
+        INSERT INTO thai_population_aggregates FROM aggregate_lines;
+
+        DELETE FROM thai_population p
+        USING aggregate_lines a
+        WHERE p.data_year = a.data_year
+            AND p.cc_code = a.cc_code
+            AND p.ccaatt_code IS NOT DISTINCT FROM a.ccaatt_code
+            AND p.ccaattmm_code IS NOT DISTINCT FROM a.ccaattmm_code;",
+    )?;
+    conn.query_row("SELECT count(*) FROM aggregate_lines", [], |row| row.get(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsers::population::PopulationRow;
+    use crate::schema::RowSchema;
+    use duckdb::params;
+
+    fn warehouse() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(
+            &format!(
+                "CREATE TABLE thai_population (data_year INTEGER, {})",
+                PopulationRow::column_definitions()
+            ),
+            [],
+        )
+        .unwrap();
+        for (year, cc_code, district, subdistrict, male, female, house) in [
+            (2022, 0, "0", "0", 100, 120, 50),
+            (2022, 10, "0", "0", 60, 70, 30),
+            (2022, 38, "0", "0", 40, 50, 20),
+            (2023, 0, "0", "0", 100, 100, 50),
+            (2023, 38, "0", "0", 100, 90, 50),
+            (2023, 38, "380100", "0", 100, 90, 40),
+            (2023, 38, "380100", "38010001", 100, 90, 40),
+        ] {
+            conn.execute(
+                &format!(
+                    "INSERT INTO thai_population (data_year, {}) VALUES (?, {})",
+                    PopulationRow::column_list(),
+                    PopulationRow::placeholders()
+                ),
+                params![
+                    year,
+                    "6612",
+                    cc_code,
+                    "p",
+                    "0",
+                    "",
+                    district,
+                    "",
+                    subdistrict,
+                    "",
+                    male,
+                    female,
+                    male + female,
+                    house
+                ],
+            )
+            .unwrap();
+        }
+        conn
+    }
+
+    #[test]
+    fn test_sums_of_children_are_compared_with_their_parent() {
+        let conn = warehouse();
+        let found = reconcile(&conn)
+            .unwrap()
+            .into_iter()
+            .map(|m| {
+                (
+                    m.data_year,
+                    m.level,
+                    m.area_code,
+                    m.column,
+                    m.published - m.summed,
+                )
+            })
+            .collect::<Vec<(i32, String, String, String, i64)>>();
+        let mismatch = |year, level: &str, code: &str, column: &str, difference| {
+            (
+                year,
+                level.to_string(),
+                code.to_string(),
+                column.to_string(),
+                difference,
+            )
+        };
+        assert_eq!(
+            found,
+            vec![
+                mismatch(2023, "nation", "0", "female", 10),
+                mismatch(2023, "nation", "0", "total", 10),
+                mismatch(2023, "province", "38", "house", 10),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_aggregate_lines_are_moved_out() {
+        let conn = warehouse();
+        assert_eq!(separate_aggregates(&conn).unwrap(), 4);
+        let remaining: (i64, i64) = conn
+            .query_row(
+                "SELECT count(*), sum(total) FROM thai_population",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(remaining, (3, 130 + 90 + 190));
+        // Moving again keeps a single copy of the aggregate lines
+        assert_eq!(separate_aggregates(&conn).unwrap(), 0);
+        let aggregates: i64 = conn
+            .query_row(
+                "SELECT count(*) FROM thai_population_aggregates",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(aggregates, 4);
+        assert!(reconcile(&conn).unwrap().is_empty());
+    }
+}
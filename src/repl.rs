@@ -0,0 +1,375 @@
+use crate::geography::Region;
+use crate::table_functions::register_table_functions;
+use duckdb::Connection;
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+use std::path::Path;
+use thiserror::Error;
+use unicode_width::UnicodeWidthStr;
+
+/// File keeping the statements typed in previous sessions.
+pub const HISTORY_PATH: &str = "./datasets/.repl_history";
+
+const HELP: &str = "\
+.export PATH QUERY  Writes the result of QUERY to PATH, as CSV or Parquet after its extension
+.help               Shows this message
+.quit               Leaves the REPL
+.tables             Lists the tables and views
+
+Views: latest_year, region_totals, sex_ratio. `FROM read_dopa_stat('stat_c66.txt')` reads a raw
+DOPA file. Statements end with `;`.";
+
+#[derive(Error, Debug)]
+pub enum ReplError {
+    #[error("Error connecting to DuckDB: {0}")]
+    DuckDB(#[from] duckdb::Error),
+    #[error("Readline error: {0}")]
+    Readline(#[from] ReadlineError),
+    #[error("{0}")]
+    Usage(String),
+}
+
+/// Creates a `thai_population` view over a Hive partitioned export, so the REPL works without
+/// the warehouse.
+pub fn attach_hive_export(conn: &Connection, root: &Path) -> duckdb::Result<()> {
+    let glob = root.join("*").join("*.parquet*");
+    conn.execute_batch(&format!(
+        "CREATE OR REPLACE TEMP VIEW thai_population AS
+        SELECT * REPLACE (CAST(data_year AS INTEGER) AS data_year)
+        FROM read_parquet('{}', hive_partitioning = true)",
+        glob.display().to_string().replace('\'', "''")
+    ))
+}
+
+/// Registers the convenience views of the REPL over `thai_population`.
+///
+/// They are temporary, so the warehouse itself is left untouched.
+pub fn register_views(conn: &Connection) -> duckdb::Result<()> {
+    conn.execute_batch(&format!(
+        "CREATE OR REPLACE TEMP VIEW latest_year AS
+        SELECT * FROM thai_population
+        WHERE data_year = (SELECT max(data_year) FROM thai_population);
+
+        CREATE OR REPLACE TEMP VIEW region_totals AS
+        SELECT
+            data_year,
+            {} AS region,
+            CAST(sum(male) AS BIGINT) AS male,
+            CAST(sum(female) AS BIGINT) AS female,
+            CAST(sum(total) AS BIGINT) AS total,
+            CAST(sum(house) AS BIGINT) AS house
+        FROM thai_population
+        WHERE cc_code <> 0
+        GROUP BY ALL
+        ORDER BY data_year, region;
+
+        CREATE OR REPLACE TEMP VIEW sex_ratio AS
+        SELECT
+            data_year,
+            cc_code,
+            arg_max(cc_desc, total) AS cc_desc,
+            round(sum(male) / nullif(sum(female), 0) * 100, 2) AS sex_ratio
+        FROM thai_population
+        GROUP BY data_year, cc_code
+        ORDER BY data_year, cc_code;",
+        Region::sql_case("cc_code")
+    ))
+}
+
+/// Renders rows as a text table, padding on the display width of the cells so Thai names,
+/// whose vowel and tone marks take no column, line up.
+pub fn render_table(columns: &[String], rows: &[Vec<Option<String>>]) -> String {
+    let cells = rows
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|cell| cell.clone().unwrap_or_else(|| "NULL".to_string()))
+                .collect::<Vec<String>>()
+        })
+        .collect::<Vec<Vec<String>>>();
+    let widths = columns
+        .iter()
+        .enumerate()
+        .map(|(index, column)| {
+            cells
+                .iter()
+                .map(|row| row[index].width())
+                .chain([column.width()])
+                .max()
+                .unwrap_or(0)
+        })
+        .collect::<Vec<usize>>();
+    let line = |values: &[String]| {
+        values
+            .iter()
+            .zip(&widths)
+            .map(|(value, width)| format!("{}{}", value, " ".repeat(width - value.width())))
+            .collect::<Vec<String>>()
+            .join(" | ")
+            .trim_end()
+            .to_string()
+    };
+    let mut out = vec![
+        line(columns),
+        widths
+            .iter()
+            .map(|width| "-".repeat(*width))
+            .collect::<Vec<String>>()
+            .join("-+-"),
+    ];
+    out.extend(cells.iter().map(|row| line(row)));
+    out.push(format!(
+        "({} row{})",
+        rows.len(),
+        if rows.len() == 1 { "" } else { "s" }
+    ));
+    out.join("\n")
+}
+
+/// An interactive SQL session over the warehouse or a Hive export.
+pub struct Repl {
+    conn: Connection,
+}
+
+impl Repl {
+    /// Starts a session over `conn`, which must hold `thai_population`, and registers the
+    /// convenience views and `read_dopa_stat`.
+    pub fn new(conn: Connection) -> duckdb::Result<Self> {
+        register_views(&conn)?;
+        register_table_functions(&conn)?;
+        Ok(Repl { conn })
+    }
+
+    /// Runs a statement or a dot command and returns what to print.
+    pub fn execute(&self, input: &str) -> Result<String, ReplError> {
+        let input = input.trim().trim_end_matches(';').trim();
+        if let Some(command) = input.strip_prefix('.') {
+            return self.dot_command(command);
+        }
+        if input.is_empty() {
+            return Ok(String::new());
+        }
+        // Anything returning rows is read as text, other statements are just executed
+        let Ok(mut stmt) = self
+            .conn
+            .prepare(&format!("SELECT COLUMNS(*)::VARCHAR FROM ({})", input))
+        else {
+            self.conn.execute_batch(input)?;
+            return Ok("OK".to_string());
+        };
+        let mut rows = stmt.query([])?;
+        let columns = rows
+            .as_ref()
+            .map(|stmt| stmt.column_names())
+            .unwrap_or_default();
+        let mut values = vec![];
+        while let Some(row) = rows.next()? {
+            values.push(
+                (0..columns.len())
+                    .map(|index| row.get(index))
+                    .collect::<duckdb::Result<Vec<Option<String>>>>()?,
+            );
+        }
+        Ok(render_table(&columns, &values))
+    }
+
+    fn dot_command(&self, command: &str) -> Result<String, ReplError> {
+        let (name, args) = command.split_once(' ').unwrap_or((command, ""));
+        match name {
+            "help" => Ok(HELP.to_string()),
+            "tables" => self.execute(
+                "SELECT table_name AS name, 'table' AS kind FROM duckdb_tables()
+                UNION ALL
+                SELECT view_name, 'view' FROM duckdb_views() WHERE NOT internal
+                ORDER BY name",
+            ),
+            "export" => {
+                let (path, query) = args
+                    .trim()
+                    .split_once(' ')
+                    .ok_or_else(|| ReplError::Usage("Usage: .export PATH QUERY".to_string()))?;
+                let options = match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+                    Some("csv") => "FORMAT CSV, HEADER",
+                    Some("parquet") => "FORMAT PARQUET",
+                    _ => {
+                        return Err(ReplError::Usage(format!(
+                            "Cannot tell the format of {}, use .csv or .parquet",
+                            path
+                        )))
+                    }
+                };
+                let rows = self.conn.execute(
+                    &format!(
+                        "COPY ({}) TO '{}' ({})",
+                        query.trim().trim_end_matches(';'),
+                        path.replace('\'', "''"),
+                        options
+                    ),
+                    [],
+                )?;
+                Ok(format!("Exported {} rows to {}", rows, path))
+            }
+            _ => Err(ReplError::Usage(format!(
+                "Unknown command .{}, see .help",
+                name
+            ))),
+        }
+    }
+
+    /// Reads statements from the terminal until `.quit` or Ctrl-D, keeping the history in
+    /// `history`.
+    ///
+    /// A statement may span several lines and ends with `;`, dot commands end with the line.
+    pub fn run(&self, history: &Path) -> Result<(), ReplError> {
+        let mut editor = DefaultEditor::new()?;
+        // The history is missing on the first session
+        let _ = editor.load_history(history);
+        println!("Connected, `.help` for help.");
+        let mut statement = String::new();
+        loop {
+            let prompt = if statement.is_empty() {
+                "rust-hive> "
+            } else {
+                "       ...> "
+            };
+            let line = match editor.readline(prompt) {
+                Ok(line) => line,
+                Err(ReadlineError::Interrupted) => {
+                    statement.clear();
+                    continue;
+                }
+                Err(ReadlineError::Eof) => break,
+                Err(e) => return Err(e.into()),
+            };
+            if statement.is_empty() && line.trim() == ".quit" {
+                break;
+            }
+            statement.push_str(&line);
+            statement.push('\n');
+            let trimmed = statement.trim();
+            if !(trimmed.starts_with('.') || trimmed.ends_with(';') || trimmed.is_empty()) {
+                continue;
+            }
+            let _ = editor.add_history_entry(trimmed);
+            match self.execute(trimmed) {
+                Ok(output) if output.is_empty() => {}
+                Ok(output) => println!("{}", output),
+                Err(e) => eprintln!("{}", e),
+            }
+            statement.clear();
+        }
+        editor.save_history(history)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsers::population::PopulationRow;
+    use crate::schema::RowSchema;
+    use duckdb::params;
+    use std::{env, fs};
+
+    fn repl() -> Repl {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(
+            &format!(
+                "CREATE TABLE thai_population (data_year INTEGER, {})",
+                PopulationRow::column_definitions()
+            ),
+            [],
+        )
+        .unwrap();
+        for (year, cc_code, desc, male, female) in [
+            (2022, 10, "กรุงเทพมหานคร", 48, 52),
+            (2023, 10, "กรุงเทพมหานคร", 47, 53),
+            (2023, 38, "บึงกาฬ", 50, 50),
+        ] {
+            conn.execute(
+                &format!(
+                    "INSERT INTO thai_population (data_year, {}) VALUES (?, {})",
+                    PopulationRow::column_list(),
+                    PopulationRow::placeholders()
+                ),
+                params![
+                    year,
+                    "6612",
+                    cc_code,
+                    desc,
+                    "0",
+                    "",
+                    "0",
+                    "",
+                    "0",
+                    "",
+                    male,
+                    female,
+                    male + female,
+                    1
+                ],
+            )
+            .unwrap();
+        }
+        Repl::new(conn).unwrap()
+    }
+
+    #[test]
+    fn test_views_and_thai_alignment() {
+        let repl = repl();
+        let output = repl
+            .execute("SELECT cc_code, cc_desc, sex_ratio FROM sex_ratio WHERE data_year = 2023;")
+            .unwrap();
+        assert_eq!(
+            output,
+            "cc_code | cc_desc      | sex_ratio\n\
+            --------+--------------+----------\n\
+            10      | กรุงเทพมหานคร | 88.68\n\
+            38      | บึงกาฬ        | 100.0\n\
+            (2 rows)"
+        );
+        let output = repl
+            .execute("SELECT count(*) AS n FROM latest_year")
+            .unwrap();
+        assert!(output.contains("\n2\n"));
+        let output = repl
+            .execute("SELECT region, total FROM region_totals")
+            .unwrap();
+        assert!(output.contains("northeast | 100"));
+        assert_eq!(repl.execute("CREATE TABLE t (x INTEGER)").unwrap(), "OK");
+        let tables = repl.execute(".tables").unwrap();
+        assert!(tables
+            .lines()
+            .any(|line| line.starts_with("sex_ratio ") && line.ends_with("| view")));
+        assert!(repl.execute(".unknown").is_err());
+    }
+
+    #[test]
+    fn test_export_to_csv_and_parquet() {
+        let repl = repl();
+        let dir = env::temp_dir().join(format!("rust_hive_repl_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let csv = dir.join("latest.csv");
+        let output = repl
+            .execute(&format!(
+                ".export {} SELECT cc_code FROM latest_year",
+                csv.display()
+            ))
+            .unwrap();
+        assert_eq!(output, format!("Exported 2 rows to {}", csv.display()));
+        assert_eq!(fs::read_to_string(&csv).unwrap(), "cc_code\n10\n38\n");
+
+        let parquet = dir.join("latest.parquet");
+        repl.execute(&format!(
+            ".export {} SELECT * FROM latest_year",
+            parquet.display()
+        ))
+        .unwrap();
+        let output = repl
+            .execute(&format!("SELECT count(*) FROM '{}'", parquet.display()))
+            .unwrap();
+        assert!(output.contains("\n2\n"));
+        assert!(repl.execute(".export out.txt SELECT 1").is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,148 @@
+use duckdb::{Row, ToSql};
+
+/// A column of a table, named after the field it is read into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Column {
+    pub name: &'static str,
+    pub sql_type: &'static str,
+}
+
+/// Describes how a row struct maps onto DuckDB columns.
+///
+/// Implemented by `row_schema!`, so the DDL, the insert binding, the row reading and the
+/// export column list all come from the struct declaration and cannot drift apart.
+pub trait RowSchema: Sized {
+    /// The columns of the struct, in declaration order.
+    const COLUMNS: &'static [Column];
+
+    /// The values of the fields, in the order of `COLUMNS`.
+    fn to_params(&self) -> Vec<&dyn ToSql>;
+
+    /// Reads the fields from `row`, starting at column `offset`.
+    fn from_row(row: &Row, offset: usize) -> duckdb::Result<Self>;
+
+    /// The column names separated by commas, e.g. for a `SELECT` or an `INSERT`.
+    fn column_list() -> String {
+        Self::COLUMNS
+            .iter()
+            .map(|column| column.name)
+            .collect::<Vec<&str>>()
+            .join(", ")
+    }
+
+    /// The column definitions of a `CREATE TABLE`.
+    fn column_definitions() -> String {
+        Self::COLUMNS
+            .iter()
+            .map(|column| format!("{} {}", column.name, column.sql_type))
+            .collect::<Vec<String>>()
+            .join(",\n")
+    }
+
+    /// One `?` placeholder per column.
+    fn placeholders() -> String {
+        vec!["?"; Self::COLUMNS.len()].join(", ")
+    }
+}
+
+/// Declares a struct whose fields are annotated with their SQL type, and implements
+/// `Serialize`, `Deserialize` and `RowSchema` for it.
+///
+/// ```ignore
+/// row_schema! {
+///     pub struct Count {
+///         pub name: String => "TEXT",
+///         pub value: i32 => "INTEGER",
+///     }
+/// }
+/// ```
+macro_rules! row_schema {
+    (
+        $(#[$meta:meta])*
+        pub struct $name:ident {
+            $($(#[$field_meta:meta])* pub $field:ident: $ty:ty => $sql_type:literal),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(serde::Serialize, serde::Deserialize)]
+        pub struct $name {
+            $($(#[$field_meta])* pub $field: $ty),*
+        }
+
+        impl $crate::schema::RowSchema for $name {
+            const COLUMNS: &'static [$crate::schema::Column] = &[
+                $($crate::schema::Column {
+                    name: stringify!($field),
+                    sql_type: $sql_type,
+                }),*
+            ];
+
+            fn to_params(&self) -> Vec<&dyn duckdb::ToSql> {
+                vec![$(&self.$field as &dyn duckdb::ToSql),*]
+            }
+
+            #[allow(unused_assignments)]
+            fn from_row(row: &duckdb::Row, offset: usize) -> duckdb::Result<Self> {
+                let mut index = offset;
+                Ok($name {
+                    $($field: {
+                        let value = row.get(index)?;
+                        index += 1;
+                        value
+                    }),*
+                })
+            }
+        }
+    };
+}
+
+pub(crate) use row_schema;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use duckdb::{params_from_iter, Connection};
+
+    row_schema! {
+        #[derive(Debug, PartialEq)]
+        pub struct Count {
+            pub name: String => "TEXT",
+            pub value: i32 => "INTEGER",
+        }
+    }
+
+    #[test]
+    fn test_row_schema_round_trip() {
+        assert_eq!(Count::column_list(), "name, value");
+        assert_eq!(Count::column_definitions(), "name TEXT,\nvalue INTEGER");
+
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(
+            &format!(
+                "CREATE TABLE counts (id INTEGER, {})",
+                Count::column_definitions()
+            ),
+            [],
+        )
+        .unwrap();
+        let count = Count {
+            name: "a".to_string(),
+            value: 3,
+        };
+        conn.execute(
+            &format!(
+                "INSERT INTO counts ({}) VALUES ({})",
+                Count::column_list(),
+                Count::placeholders()
+            ),
+            params_from_iter(count.to_params()),
+        )
+        .unwrap();
+        let read = conn
+            .query_row("SELECT id, name, value FROM counts", [], |row| {
+                Count::from_row(row, 1)
+            })
+            .unwrap();
+        assert_eq!(read, count);
+    }
+}
@@ -0,0 +1,522 @@
+use crate::calendar::validate_data_year;
+use crate::codes::ProvinceCode;
+use crate::graphql::{build_schema, PopulationSchema};
+use crate::parsers::population::PopulationRow;
+use crate::query::{provinces, AggregateLevel, PopulationQuery};
+use crate::schema::RowSchema;
+use duckdb::Connection;
+use futures::executor::block_on;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tracing::{info, warn};
+
+/// Default address of `serve`.
+pub const DEFAULT_SERVE_ADDR: &str = "127.0.0.1:8080";
+/// Rows per page when `per_page` is not given.
+pub const DEFAULT_PAGE_SIZE: usize = 100;
+/// Largest `per_page` accepted.
+pub const MAX_PAGE_SIZE: usize = 1000;
+/// Cached results are dropped all at once past this many entries.
+const MAX_CACHE_ENTRIES: usize = 256;
+
+const PROVINCE_COLUMNS: &[&str] = &["cc_code", "cc_desc", "region", "first_year", "last_year"];
+const AGGREGATE_COLUMNS: &[&str] = &["data_year", "key", "male", "female", "total", "house"];
+
+#[derive(Error, Debug)]
+pub enum ApiError {
+    #[error("Bad request: {0}")]
+    BadRequest(String),
+    #[error("Not found: {0}")]
+    NotFound(String),
+    #[error("Method not allowed: {0}")]
+    MethodNotAllowed(String),
+    #[error("Error querying DuckDB: {0}")]
+    DuckDB(#[from] duckdb::Error),
+}
+
+impl ApiError {
+    fn status(&self) -> u16 {
+        match self {
+            ApiError::BadRequest(_) => 400,
+            ApiError::NotFound(_) => 404,
+            ApiError::MethodNotAllowed(_) => 405,
+            ApiError::DuckDB(_) => 500,
+        }
+    }
+}
+
+/// Representation of a response body, chosen by `format=` or else the `Accept` header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Csv,
+}
+
+/// An HTTP response of the API.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub headers: Vec<(&'static str, String)>,
+    pub body: String,
+}
+
+impl Response {
+    fn error(error: &ApiError) -> Self {
+        Response {
+            status: error.status(),
+            content_type: "application/json",
+            headers: vec![],
+            body: json!({ "error": error.to_string() }).to_string(),
+        }
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            _ => "Internal Server Error",
+        }
+    }
+
+    /// The response as written on the wire.
+    pub fn to_http(&self) -> String {
+        let mut head = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: {}; charset=utf-8\r\nContent-Length: {}\r\n",
+            self.status,
+            self.reason(),
+            self.content_type,
+            self.body.len()
+        );
+        for (name, value) in &self.headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        format!("{}Connection: close\r\n\r\n{}", head, self.body)
+    }
+}
+
+/// Rows of an endpoint, with the column order used by the CSV output.
+#[derive(Debug)]
+struct Table {
+    columns: Vec<&'static str>,
+    rows: Vec<Value>,
+}
+
+impl Table {
+    fn new<T: Serialize>(columns: Vec<&'static str>, rows: &[T]) -> Self {
+        Table {
+            columns,
+            rows: rows
+                .iter()
+                .map(|row| serde_json::to_value(row).expect("rows serialize to JSON"))
+                .collect(),
+        }
+    }
+
+    fn to_csv(&self, rows: &[Value]) -> String {
+        let mut out = self.columns.join(",");
+        out.push('\n');
+        for row in rows {
+            let cells = self
+                .columns
+                .iter()
+                .map(|column| csv_cell(&row[*column]))
+                .collect::<Vec<String>>();
+            out.push_str(&cells.join(","));
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/// Renders a JSON value as a CSV cell, quoting text holding separators or quotes.
+fn csv_cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(text) if text.contains([',', '"', '\n', '\r']) => {
+            format!("\"{}\"", text.replace('"', "\"\""))
+        }
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// The path and query parameters of a request target, e.g. `/population?year=2023`.
+#[derive(Debug, Clone, PartialEq)]
+struct Target {
+    path: String,
+    params: Vec<(String, String)>,
+}
+
+impl Target {
+    fn parse(target: &str) -> Self {
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let params = query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+                (name.to_string(), value.replace('+', " "))
+            })
+            .collect();
+        Target {
+            path: path.trim_end_matches('/').to_string(),
+            params,
+        }
+    }
+
+    fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(param, _)| param == name)
+            .map(|(_, value)| value.as_str())
+            .filter(|value| !value.is_empty())
+    }
+
+    fn parse_param<T: std::str::FromStr>(&self, name: &str) -> Result<Option<T>, ApiError>
+    where
+        T::Err: std::fmt::Display,
+    {
+        self.param(name)
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|e| ApiError::BadRequest(format!("{}={}: {}", name, value, e)))
+            })
+            .transpose()
+    }
+
+    /// The key of the rows in the cache: the path and the filters, without the paging and
+    /// the format which are applied after the cache.
+    fn cache_key(&self) -> String {
+        let mut filters = self
+            .params
+            .iter()
+            .filter(|(name, _)| !["page", "per_page", "format"].contains(&name.as_str()))
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<String>>();
+        filters.sort();
+        format!("{}?{}", self.path, filters.join("&"))
+    }
+}
+
+/// A small read-only REST API over the warehouse.
+///
+/// * `GET /provinces` - the provinces with their latest name and region.
+/// * `GET /population?year=&cc_code=` - the rows of `thai_population`, both filters optional.
+/// * `GET /timeseries/{cc_code}` - the yearly totals of a province.
+/// * `POST /graphql` - the GraphQL schema of `graphql`, for drilling down the areas.
+///
+/// Lists are paginated with `page` (from 1) and `per_page`, and returned as JSON or, with
+/// `format=csv` or `Accept: text/csv`, as CSV. Results are cached for `cache_ttl`, the
+/// warehouse only changing when an ingestion runs.
+pub struct ApiServer {
+    conn: Connection,
+    schema: PopulationSchema,
+    cache_ttl: Duration,
+    cache: HashMap<String, (Instant, Arc<Table>)>,
+}
+
+impl ApiServer {
+    pub fn new(conn: Connection, cache_ttl: Duration) -> duckdb::Result<Self> {
+        Ok(ApiServer {
+            schema: build_schema(conn.try_clone()?),
+            conn,
+            cache_ttl,
+            cache: HashMap::new(),
+        })
+    }
+
+    /// Answers a request, given its method, its target, its `Accept` header and its body.
+    pub fn handle(
+        &mut self,
+        method: &str,
+        target: &str,
+        accept: Option<&str>,
+        body: &str,
+    ) -> Response {
+        let target = Target::parse(target);
+        let response = if target.path == "/graphql" {
+            self.graphql(method, body)
+        } else {
+            self.respond(method, &target, accept)
+        };
+        match response {
+            Ok(response) => response,
+            Err(e) => {
+                if let ApiError::DuckDB(_) = e {
+                    warn!(error = %e, path = %target.path, "Request failed");
+                }
+                Response::error(&e)
+            }
+        }
+    }
+
+    /// Executes a GraphQL request, posted as `{"query": ..., "variables": ...}`.
+    fn graphql(&self, method: &str, body: &str) -> Result<Response, ApiError> {
+        if method != "POST" {
+            return Err(ApiError::MethodNotAllowed(method.to_string()));
+        }
+        let request = serde_json::from_str::<async_graphql::Request>(body)
+            .map_err(|e| ApiError::BadRequest(e.to_string()))?;
+        let response = block_on(self.schema.execute(request));
+        Ok(Response {
+            status: 200,
+            content_type: "application/json",
+            headers: vec![],
+            body: serde_json::to_string(&response).expect("responses serialize to JSON"),
+        })
+    }
+
+    fn respond(
+        &mut self,
+        method: &str,
+        target: &Target,
+        accept: Option<&str>,
+    ) -> Result<Response, ApiError> {
+        if method != "GET" {
+            return Err(ApiError::MethodNotAllowed(method.to_string()));
+        }
+        let format = match target.param("format") {
+            Some("json") => Format::Json,
+            Some("csv") => Format::Csv,
+            Some(other) => return Err(ApiError::BadRequest(format!("Unknown format: {}", other))),
+            None if accept.is_some_and(|accept| accept.contains("text/csv")) => Format::Csv,
+            None => Format::Json,
+        };
+        let page = target.parse_param::<usize>("page")?.unwrap_or(1).max(1);
+        let per_page = target
+            .parse_param::<usize>("per_page")?
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE);
+
+        let table = self.cached_table(target)?;
+        let total = table.rows.len();
+        let start = ((page - 1) * per_page).min(total);
+        let rows = &table.rows[start..(start + per_page).min(total)];
+        let headers = vec![
+            ("X-Total-Count", total.to_string()),
+            (
+                "Cache-Control",
+                format!("max-age={}", self.cache_ttl.as_secs()),
+            ),
+        ];
+        Ok(match format {
+            Format::Json => Response {
+                status: 200,
+                content_type: "application/json",
+                headers,
+                body: json!({
+                    "data": rows,
+                    "page": page,
+                    "per_page": per_page,
+                    "total": total,
+                })
+                .to_string(),
+            },
+            Format::Csv => Response {
+                status: 200,
+                content_type: "text/csv",
+                headers,
+                body: table.to_csv(rows),
+            },
+        })
+    }
+
+    /// Returns the rows of the target from the cache, querying the warehouse when they are
+    /// missing or older than `cache_ttl`.
+    fn cached_table(&mut self, target: &Target) -> Result<Arc<Table>, ApiError> {
+        let key = target.cache_key();
+        if let Some((cached_at, table)) = self.cache.get(&key) {
+            if cached_at.elapsed() < self.cache_ttl {
+                return Ok(Arc::clone(table));
+            }
+        }
+        let table = Arc::new(self.query(target)?);
+        if self.cache.len() >= MAX_CACHE_ENTRIES {
+            self.cache.clear();
+        }
+        self.cache.insert(key, (Instant::now(), Arc::clone(&table)));
+        Ok(table)
+    }
+
+    fn query(&self, target: &Target) -> Result<Table, ApiError> {
+        let segments = target.path.split('/').skip(1).collect::<Vec<&str>>();
+        match segments.as_slice() {
+            ["provinces"] => Ok(Table::new(
+                PROVINCE_COLUMNS.to_vec(),
+                &provinces(&self.conn)?,
+            )),
+            ["population"] => {
+                let mut query = PopulationQuery::new();
+                if let Some(year) = target.parse_param::<i32>("year")? {
+                    let year = validate_data_year(year)
+                        .map_err(|e| ApiError::BadRequest(e.to_string()))?;
+                    query = query.years(year..=year);
+                }
+                if let Some(code) = target.parse_param::<ProvinceCode>("cc_code")? {
+                    query = query.province(code);
+                }
+                let columns = ["data_year"]
+                    .into_iter()
+                    .chain(PopulationRow::COLUMNS.iter().map(|column| column.name))
+                    .collect();
+                Ok(Table::new(columns, &query.fetch(&self.conn)?))
+            }
+            ["timeseries", code] => {
+                let code = code
+                    .parse::<ProvinceCode>()
+                    .map_err(|e| ApiError::BadRequest(e.to_string()))?;
+                let rows = PopulationQuery::new()
+                    .province(code)
+                    .aggregate(&self.conn, AggregateLevel::Province)?;
+                if rows.is_empty() {
+                    return Err(ApiError::NotFound(format!("province {}", code)));
+                }
+                Ok(Table::new(AGGREGATE_COLUMNS.to_vec(), &rows))
+            }
+            _ => Err(ApiError::NotFound(target.path.clone())),
+        }
+    }
+
+    /// Serves the API on `addr` until the process is stopped, one request at a time.
+    pub fn serve(mut self, addr: &str) -> io::Result<()> {
+        let listener = TcpListener::bind(addr)?;
+        info!(addr = %addr, "Serving the warehouse API");
+        for mut stream in listener.incoming().flatten() {
+            let mut reader = BufReader::new(&stream);
+            let mut request_line = String::new();
+            if reader.read_line(&mut request_line).is_err() {
+                continue;
+            }
+            let mut accept = None;
+            let mut content_length = 0;
+            let mut header = String::new();
+            while reader.read_line(&mut header).is_ok_and(|read| read > 0) {
+                let line = header.trim_end();
+                if line.is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("accept") {
+                        accept = Some(value.trim().to_string());
+                    } else if name.eq_ignore_ascii_case("content-length") {
+                        content_length = value.trim().parse().unwrap_or(0);
+                    }
+                }
+                header.clear();
+            }
+            let mut body = vec![0; content_length];
+            if reader.read_exact(&mut body).is_err() {
+                continue;
+            }
+            let body = String::from_utf8_lossy(&body);
+            let mut parts = request_line.split_whitespace();
+            let (method, target) = (parts.next().unwrap_or(""), parts.next().unwrap_or("/"));
+            let response = self.handle(method, target, accept.as_deref(), &body);
+            info!(method, target, status = response.status, "Handled request");
+            let _ = stream.write_all(response.to_http().as_bytes());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use duckdb::params;
+
+    fn server() -> ApiServer {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(
+            &format!(
+                "CREATE TABLE thai_population (data_year INTEGER, {})",
+                PopulationRow::column_definitions()
+            ),
+            [],
+        )
+        .unwrap();
+        for (year, cc_code, desc, total) in [
+            (2022, 0, "Country", 300),
+            (2022, 10, "Bangkok", 100),
+            (2022, 38, "Bueng Kan", 200),
+            (2023, 10, "Bangkok, Krung Thep", 120),
+            (2023, 38, "Bueng Kan", 210),
+        ] {
+            conn.execute(
+                &format!(
+                    "INSERT INTO thai_population (data_year, {}) VALUES (?, {})",
+                    PopulationRow::column_list(),
+                    PopulationRow::placeholders()
+                ),
+                params![year, "6612", cc_code, desc, "0", "", "0", "", "0", "", 1, 2, total, 4],
+            )
+            .unwrap();
+        }
+        ApiServer::new(conn, Duration::from_secs(60)).unwrap()
+    }
+
+    #[test]
+    fn test_population_is_filtered_and_paginated() {
+        let mut server = server();
+        let response = server.handle("GET", "/population?year=2023&per_page=1&page=2", None, "");
+        assert_eq!(response.status, 200);
+        let body: Value = serde_json::from_str(&response.body).unwrap();
+        assert_eq!(body["total"], 2);
+        assert_eq!(body["data"].as_array().unwrap().len(), 1);
+        assert_eq!(body["data"][0]["cc_code"], "38");
+
+        let csv = server.handle("GET", "/population?cc_code=10", Some("text/csv"), "");
+        assert_eq!(csv.content_type, "text/csv");
+        let lines = csv.body.lines().collect::<Vec<&str>>();
+        assert!(lines[0].starts_with("data_year,yymm,cc_code,cc_desc,"));
+        assert_eq!(lines.len(), 3);
+        assert!(lines[2].starts_with("2023,6612,10,\"Bangkok, Krung Thep\","));
+    }
+
+    #[test]
+    fn test_provinces_timeseries_and_errors() {
+        let mut server = server();
+        let body: Value =
+            serde_json::from_str(&server.handle("GET", "/provinces", None, "").body).unwrap();
+        assert_eq!(body["total"], 2);
+        assert_eq!(body["data"][1]["region"], "northeast");
+        assert_eq!(body["data"][0]["cc_desc"], "Bangkok, Krung Thep");
+
+        let series = server.handle("GET", "/timeseries/38?format=csv", None, "");
+        assert_eq!(
+            series.body,
+            "data_year,key,male,female,total,house\n2022,38,1,2,200,4\n2023,38,1,2,210,4\n"
+        );
+
+        assert_eq!(server.handle("GET", "/timeseries/77", None, "").status, 404);
+        assert_eq!(server.handle("GET", "/timeseries/x", None, "").status, 400);
+        assert_eq!(
+            server
+                .handle("GET", "/population?year=1800", None, "")
+                .status,
+            400
+        );
+        assert_eq!(server.handle("POST", "/provinces", None, "").status, 405);
+        assert_eq!(server.handle("GET", "/missing", None, "").status, 404);
+
+        let graphql = server.handle(
+            "POST",
+            "/graphql",
+            None,
+            r#"{"query": "{ area(code: \"38\") { name children { code } } }"}"#,
+        );
+        let body: Value = serde_json::from_str(&graphql.body).unwrap();
+        assert_eq!(body["data"]["area"]["name"], "Bueng Kan");
+        assert_eq!(server.handle("GET", "/graphql", None, "").status, 405);
+        assert_eq!(server.handle("POST", "/graphql", None, "{").status, 400);
+    }
+}
@@ -0,0 +1,201 @@
+use crate::parsers::population::{parse_lines, PopulationRow};
+use crate::schema::RowSchema;
+use duckdb::core::{DataChunkHandle, Inserter, LogicalTypeHandle, LogicalTypeId};
+use duckdb::vtab::{BindInfo, Free, FunctionInfo, InitInfo, VTab};
+use duckdb::Connection;
+use std::error::Error;
+use std::fs;
+use std::ptr;
+
+/// Name of the table function reading a DOPA statistic file.
+pub const READ_DOPA_STAT: &str = "read_dopa_stat";
+
+/// Registers `read_dopa_stat(path, ignore_errors := false)` on the connection, returning the
+/// lines of a DOPA statistic file with the columns of `PopulationRow`.
+///
+/// A line which does not parse fails the query, unless `ignore_errors` is set, in which case
+/// it is skipped.
+///
+/// # Arguments
+///
+/// * `conn` - A reference to a DuckDB Connection.
+///
+/// # Returns
+///
+/// * `Result<()>` - Returns Ok(()) once the function can be called from SQL.
+///
+pub fn register_table_functions(conn: &Connection) -> duckdb::Result<()> {
+    conn.register_table_function::<ReadDopaStat>(READ_DOPA_STAT)
+}
+
+/// The rows of the file, parsed once when the query is bound.
+#[repr(C)]
+struct ReadDopaStatBind {
+    rows: *mut Vec<PopulationRow>,
+}
+
+impl Free for ReadDopaStatBind {
+    fn free(&mut self) {
+        if !self.rows.is_null() {
+            drop(unsafe { Box::from_raw(self.rows) });
+            self.rows = ptr::null_mut();
+        }
+    }
+}
+
+/// How many rows were already returned.
+#[repr(C)]
+struct ReadDopaStatInit {
+    offset: usize,
+}
+
+impl Free for ReadDopaStatInit {}
+
+struct ReadDopaStat;
+
+fn logical_type(sql_type: &str) -> LogicalTypeHandle {
+    LogicalTypeHandle::from(match sql_type {
+        "INTEGER" => LogicalTypeId::Integer,
+        _ => LogicalTypeId::Varchar,
+    })
+}
+
+impl VTab for ReadDopaStat {
+    type InitData = ReadDopaStatInit;
+    type BindData = ReadDopaStatBind;
+
+    unsafe fn bind(bind: &BindInfo, data: *mut ReadDopaStatBind) -> Result<(), Box<dyn Error>> {
+        // The bind data is freed even when binding fails
+        data.write(ReadDopaStatBind {
+            rows: ptr::null_mut(),
+        });
+        for column in PopulationRow::COLUMNS {
+            bind.add_result_column(column.name, logical_type(column.sql_type));
+        }
+        let path = bind.get_parameter(0).to_string();
+        let ignore_errors = bind
+            .get_named_parameter("ignore_errors")
+            .is_some_and(|value| value.to_string() == "true");
+        let text = fs::read_to_string(&path).map_err(|e| format!("Cannot read {}: {}", path, e))?;
+        let mut rows = vec![];
+        for (line, row) in parse_lines(&text) {
+            match row {
+                Ok(row) => rows.push(row),
+                Err(_) if ignore_errors => {}
+                Err(e) => return Err(format!("{}:{}: {}", path, line, e).into()),
+            }
+        }
+        bind.set_cardinality(rows.len() as u64, true);
+        (*data).rows = Box::into_raw(Box::new(rows));
+        Ok(())
+    }
+
+    unsafe fn init(_: &InitInfo, data: *mut ReadDopaStatInit) -> Result<(), Box<dyn Error>> {
+        data.write(ReadDopaStatInit { offset: 0 });
+        Ok(())
+    }
+
+    unsafe fn func(
+        func: &FunctionInfo,
+        output: &mut DataChunkHandle,
+    ) -> Result<(), Box<dyn Error>> {
+        let init = &mut *func.get_init_data::<ReadDopaStatInit>();
+        let rows = &*(*func.get_bind_data::<ReadDopaStatBind>()).rows;
+        let chunk_size = duckdb::ffi::duckdb_vector_size() as usize;
+        let chunk = &rows[init.offset..rows.len().min(init.offset + chunk_size)];
+
+        let mut vectors = (0..PopulationRow::COLUMNS.len())
+            .map(|index| output.flat_vector(index))
+            .collect::<Vec<_>>();
+        for (index, row) in chunk.iter().enumerate() {
+            vectors[0].insert(index, row.yymm.as_str());
+            vectors[1].as_mut_slice::<i32>()[index] = row.cc_code.value();
+            vectors[2].insert(index, row.cc_desc.as_str());
+            vectors[3].insert(index, row.rcode_code.as_str());
+            vectors[4].insert(index, row.rcode_desc.as_str());
+            vectors[5].insert(index, row.ccaatt_code.as_str());
+            vectors[6].insert(index, row.ccaatt_desc.as_str());
+            vectors[7].insert(index, row.ccaattmm_code.as_str());
+            vectors[8].insert(index, row.ccaattmm_desc.as_str());
+            vectors[9].as_mut_slice::<i32>()[index] = row.male;
+            vectors[10].as_mut_slice::<i32>()[index] = row.female;
+            vectors[11].as_mut_slice::<i32>()[index] = row.total;
+            vectors[12].as_mut_slice::<i32>()[index] = row.house;
+        }
+        output.set_len(chunk.len());
+        init.offset += chunk.len();
+        Ok(())
+    }
+
+    fn parameters() -> Option<Vec<LogicalTypeHandle>> {
+        Some(vec![LogicalTypeHandle::from(LogicalTypeId::Varchar)])
+    }
+
+    fn named_parameters() -> Option<Vec<(String, LogicalTypeHandle)>> {
+        Some(vec![(
+            "ignore_errors".to_string(),
+            LogicalTypeHandle::from(LogicalTypeId::Boolean),
+        )])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs};
+
+    #[test]
+    fn test_read_dopa_stat_parses_a_file() {
+        let dir = env::temp_dir().join(format!("rust_hive_vtab_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("stat_c66.txt");
+        let mut text =
+            "\u{feff}6612|0|ทั่วประเทศ|0| |0| |0| |32,375,532|33,811,195|66,186,727|27,224,743|\r\n"
+                .to_string();
+        for cc_code in 10..3010 {
+            text.push_str(&format!(
+                "6612|{}|p| 0| |0| |0| |1,000|1,200|2,200|700|\r\n",
+                cc_code % 100
+            ));
+        }
+        text.push_str("broken line\r\n\r\n");
+        fs::write(&path, text).unwrap();
+
+        let conn = Connection::open_in_memory().unwrap();
+        register_table_functions(&conn).unwrap();
+        let query = |options: &str| {
+            conn.query_row(
+                &format!(
+                    "SELECT count(*), sum(total), max(cc_desc) FILTER (WHERE cc_code = 0)
+                    FROM read_dopa_stat('{}'{})",
+                    path.display(),
+                    options
+                ),
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+        };
+        let error = query("").unwrap_err().to_string();
+        assert!(error.contains("stat_c66.txt:3002"), "{}", error);
+        let (rows, total, nation): (i64, i64, String) = query(", ignore_errors = true").unwrap();
+        assert_eq!(rows, 3001);
+        assert_eq!(total, 66_186_727 + 3000 * 2200);
+        assert_eq!(nation, "ทั่วประเทศ");
+        let (cc_code, rcode_code): (i32, String) = conn
+            .query_row(
+                &format!(
+                    "SELECT cc_code, rcode_code FROM read_dopa_stat('{}', ignore_errors = true)
+                    LIMIT 1 OFFSET 1",
+                    path.display()
+                ),
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!((cc_code, rcode_code.as_str()), (10, "0000"));
+        assert!(conn
+            .query_row("FROM read_dopa_stat('missing.txt')", [], |_| Ok(()))
+            .is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}